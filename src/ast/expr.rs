@@ -1,3 +1,7 @@
+use std::rc::Rc;
+
+use super::stmt::{FunctionStmt, Stmt};
+
 use crate::token::Token;
 
 // Manually writing this part out
@@ -26,6 +30,11 @@ pub enum Expr {
     Get(GetExpr),
     Set(SetExpr),
     This(ThisExpr),
+    Super(SuperExpr),
+    List(ListExpr),
+    Update(UpdateExpr),
+    Loop(LoopExpr),
+    Function(FunctionExpr),
 }
 
 impl Expr {
@@ -42,6 +51,33 @@ impl Expr {
             Self::Get(expr) => expr.id,
             Self::Set(expr) => expr.id,
             Self::This(expr) => expr.id,
+            Self::Super(expr) => expr.id,
+            Self::List(expr) => expr.id,
+            Self::Update(expr) => expr.id,
+            Self::Loop(expr) => expr.id,
+            Self::Function(expr) => expr.id,
+        };
+    }
+
+    // Where its main token is, eg. the operator of a binary expression
+    pub fn line(&self) -> usize {
+        return match self {
+            Self::Literal(expr) => expr.token.line,
+            Self::Logical(expr) => expr.operator.line,
+            Self::Unary(expr) => expr.op.1.line,
+            Self::Binary(expr) => expr.op.1.line,
+            Self::Call(expr) => expr.paren.line,
+            Self::Grouping(expr) => expr.left.line,
+            Self::Variable(expr) => expr.name.line,
+            Self::Assignment(expr) => expr.name.line,
+            Self::Get(expr) => expr.name.line,
+            Self::Set(expr) => expr.name.line,
+            Self::This(expr) => expr.keyword.line,
+            Self::Super(expr) => expr.keyword.line,
+            Self::List(expr) => expr.bracket.line,
+            Self::Update(expr) => expr.operator.line,
+            Self::Loop(expr) => expr.keyword.line,
+            Self::Function(expr) => expr.declaration.name.line,
         };
     }
 }
@@ -139,6 +175,8 @@ pub struct GetExpr {
     pub id: ExprId,
     pub object: Box<Expr>,
     pub name: Token,
+    // `a?.b`, which gives `nil` rather than an error when `a` is `nil`
+    pub optional: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -155,6 +193,46 @@ pub struct ThisExpr {
     pub keyword: Token,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct SuperExpr {
+    pub id: ExprId,
+    pub keyword: Token,
+    pub method: Token,
+}
+
+// `[a, b, c]`, building a new list each time it's evaluated
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListExpr {
+    pub id: ExprId,
+    pub bracket: Token,
+    pub elements: Vec<Expr>,
+}
+
+// `++` / `--` on a variable or property. Prefix evaluates to the new value, postfix to the old
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpdateExpr {
+    pub id: ExprId,
+    pub operator: Token,
+    // Only ever a `Variable` or a non-optional `Get`
+    pub target: Box<Expr>,
+    pub prefix: bool,
+}
+
+// A loop in expression position, evaluating to the value it `break`s with
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoopExpr {
+    pub id: ExprId,
+    pub keyword: Token,
+    pub loop_stmt: Box<Stmt>,
+}
+
+// An anonymous function, named `<anonymous@line:col>` after its `fun` keyword
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionExpr {
+    pub id: ExprId,
+    pub declaration: Rc<FunctionStmt>,
+}
+
 // Visitor pattern
 pub trait ExprVisitor<R> {
     fn visit_literal_expr(&mut self, expr: &LiteralExpr) -> R;
@@ -168,6 +246,11 @@ pub trait ExprVisitor<R> {
     fn visit_get_expr(&mut self, expr: &GetExpr) -> R;
     fn visit_set_expr(&mut self, expr: &SetExpr) -> R;
     fn visit_this_expr(&mut self, expr: &ThisExpr) -> R;
+    fn visit_super_expr(&mut self, expr: &SuperExpr) -> R;
+    fn visit_list_expr(&mut self, expr: &ListExpr) -> R;
+    fn visit_update_expr(&mut self, expr: &UpdateExpr) -> R;
+    fn visit_loop_expr(&mut self, expr: &LoopExpr) -> R;
+    fn visit_function_expr(&mut self, expr: &FunctionExpr) -> R;
 }
 
 pub trait ExprAccept<R, V: ExprVisitor<R>> {
@@ -188,6 +271,11 @@ impl<R, V: ExprVisitor<R>> ExprAccept<R, V> for Expr {
             Self::Get(expr) => expr.accept(visitor),
             Self::Set(expr) => expr.accept(visitor),
             Self::This(expr) => expr.accept(visitor),
+            Self::Super(expr) => expr.accept(visitor),
+            Self::List(expr) => expr.accept(visitor),
+            Self::Update(expr) => expr.accept(visitor),
+            Self::Loop(expr) => expr.accept(visitor),
+            Self::Function(expr) => expr.accept(visitor),
         };
     }
 }
@@ -257,3 +345,33 @@ impl<R, V: ExprVisitor<R>> ExprAccept<R, V> for ThisExpr {
         return visitor.visit_this_expr(self);
     }
 }
+
+impl<R, V: ExprVisitor<R>> ExprAccept<R, V> for SuperExpr {
+    fn accept(&self, visitor: &mut V) -> R {
+        return visitor.visit_super_expr(self);
+    }
+}
+
+impl<R, V: ExprVisitor<R>> ExprAccept<R, V> for ListExpr {
+    fn accept(&self, visitor: &mut V) -> R {
+        return visitor.visit_list_expr(self);
+    }
+}
+
+impl<R, V: ExprVisitor<R>> ExprAccept<R, V> for UpdateExpr {
+    fn accept(&self, visitor: &mut V) -> R {
+        return visitor.visit_update_expr(self);
+    }
+}
+
+impl<R, V: ExprVisitor<R>> ExprAccept<R, V> for LoopExpr {
+    fn accept(&self, visitor: &mut V) -> R {
+        return visitor.visit_loop_expr(self);
+    }
+}
+
+impl<R, V: ExprVisitor<R>> ExprAccept<R, V> for FunctionExpr {
+    fn accept(&self, visitor: &mut V) -> R {
+        return visitor.visit_function_expr(self);
+    }
+}
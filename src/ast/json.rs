@@ -0,0 +1,519 @@
+use super::{expr::*, stmt::*};
+
+use crate::{token::Token, token_type::TokenType};
+
+// Serializes a parsed program as compact JSON, for tools that want the tree without
+// parsing Lox themselves. Every node is an object with a "kind", plus a "line" when it
+// starts with a token. Field order is fixed, so the output is stable across runs.
+pub struct JsonPrinter;
+
+impl JsonPrinter {
+    pub fn print(&mut self, statements: &[Stmt]) -> String {
+        return self.stmts(statements);
+    }
+
+    // The same JSON with one field or item per line, so diffing the output of two builds
+    // points at the nodes that changed rather than at one very long line
+    pub fn print_indented(&mut self, statements: &[Stmt]) -> String {
+        return indent(&self.print(statements));
+    }
+
+    fn stmts(&mut self, statements: &[Stmt]) -> String {
+        let statements = statements
+            .iter()
+            .map(|statement| statement.accept(self))
+            .collect();
+
+        return array(statements);
+    }
+
+    fn expr(&mut self, expr: &Expr) -> String {
+        return expr.accept(self);
+    }
+
+    fn optional_expr(&mut self, expr: &Option<Expr>) -> String {
+        return match expr {
+            Some(expr) => self.expr(expr),
+            None => "null".to_string(),
+        };
+    }
+
+    fn optional_stmt(&mut self, stmt: &Option<Box<Stmt>>) -> String {
+        return match stmt {
+            Some(stmt) => stmt.accept(self),
+            None => "null".to_string(),
+        };
+    }
+
+    fn function(&mut self, function: &FunctionStmt) -> String {
+        let params = function
+            .params
+            .iter()
+            .zip(&function.defaults)
+            .map(|(param, default)| {
+                return object(&[
+                    ("name", string(&param.lexeme)),
+                    ("default", self.optional_expr(default)),
+                ]);
+            })
+            .collect();
+
+        return node(
+            "Function",
+            &function.name,
+            &[
+                ("name", string(&function.name.lexeme)),
+                ("params", array(params)),
+                ("body", self.stmts(&function.body)),
+            ],
+        );
+    }
+}
+
+impl ExprVisitor<String> for JsonPrinter {
+    fn visit_literal_expr(&mut self, expr: &LiteralExpr) -> String {
+        let value = match &expr.token.token_type {
+            TokenType::Number(value) => value.to_string(),
+            TokenType::String(value) => string(value),
+            TokenType::True => "true".to_string(),
+            TokenType::False => "false".to_string(),
+            _ => "null".to_string(),
+        };
+
+        return node("Literal", &expr.token, &[("value", value)]);
+    }
+
+    fn visit_logical_expr(&mut self, expr: &LogicalExpr) -> String {
+        return node(
+            "Logical",
+            &expr.operator,
+            &[
+                ("operator", string(&expr.operator.lexeme)),
+                ("left", self.expr(&expr.left)),
+                ("right", self.expr(&expr.right)),
+            ],
+        );
+    }
+
+    fn visit_unary_expr(&mut self, expr: &UnaryExpr) -> String {
+        return node(
+            "Unary",
+            &expr.op.1,
+            &[
+                ("operator", string(&expr.op.1.lexeme)),
+                ("right", self.expr(&expr.right)),
+            ],
+        );
+    }
+
+    fn visit_binary_expr(&mut self, expr: &BinaryExpr) -> String {
+        return node(
+            "Binary",
+            &expr.op.1,
+            &[
+                ("operator", string(&expr.op.1.lexeme)),
+                ("left", self.expr(&expr.left)),
+                ("right", self.expr(&expr.right)),
+            ],
+        );
+    }
+
+    fn visit_call_expr(&mut self, expr: &CallExpr) -> String {
+        let arguments = expr
+            .arguments
+            .iter()
+            .map(|argument| self.expr(argument))
+            .collect();
+
+        return node(
+            "Call",
+            &expr.paren,
+            &[
+                ("callee", self.expr(&expr.callee)),
+                ("arguments", array(arguments)),
+            ],
+        );
+    }
+
+    fn visit_grouping_expr(&mut self, expr: &GroupingExpr) -> String {
+        return node(
+            "Grouping",
+            &expr.left,
+            &[("expression", self.expr(&expr.expr))],
+        );
+    }
+
+    fn visit_variable_expr(&mut self, expr: &VariableExpr) -> String {
+        return node(
+            "Variable",
+            &expr.name,
+            &[("name", string(&expr.name.lexeme))],
+        );
+    }
+
+    fn visit_assignment_expr(&mut self, expr: &AssignmentExpr) -> String {
+        return node(
+            "Assign",
+            &expr.name,
+            &[
+                ("name", string(&expr.name.lexeme)),
+                ("value", self.expr(&expr.value)),
+            ],
+        );
+    }
+
+    fn visit_get_expr(&mut self, expr: &GetExpr) -> String {
+        return node(
+            "Get",
+            &expr.name,
+            &[
+                ("object", self.expr(&expr.object)),
+                ("name", string(&expr.name.lexeme)),
+                ("optional", expr.optional.to_string()),
+            ],
+        );
+    }
+
+    fn visit_set_expr(&mut self, expr: &SetExpr) -> String {
+        return node(
+            "Set",
+            &expr.name,
+            &[
+                ("object", self.expr(&expr.object)),
+                ("name", string(&expr.name.lexeme)),
+                ("value", self.expr(&expr.value)),
+            ],
+        );
+    }
+
+    fn visit_this_expr(&mut self, expr: &ThisExpr) -> String {
+        return node("This", &expr.keyword, &[]);
+    }
+
+    fn visit_super_expr(&mut self, expr: &SuperExpr) -> String {
+        return node(
+            "Super",
+            &expr.keyword,
+            &[("method", string(&expr.method.lexeme))],
+        );
+    }
+
+    fn visit_list_expr(&mut self, expr: &ListExpr) -> String {
+        let elements = expr
+            .elements
+            .iter()
+            .map(|element| self.expr(element))
+            .collect();
+
+        return node("List", &expr.bracket, &[("elements", array(elements))]);
+    }
+
+    fn visit_update_expr(&mut self, expr: &UpdateExpr) -> String {
+        return node(
+            "Update",
+            &expr.operator,
+            &[
+                ("operator", string(&expr.operator.lexeme)),
+                ("prefix", expr.prefix.to_string()),
+                ("target", self.expr(&expr.target)),
+            ],
+        );
+    }
+
+    fn visit_loop_expr(&mut self, expr: &LoopExpr) -> String {
+        return node(
+            "LoopExpression",
+            &expr.keyword,
+            &[("loop", expr.loop_stmt.accept(self))],
+        );
+    }
+
+    fn visit_function_expr(&mut self, expr: &FunctionExpr) -> String {
+        return self.function(&expr.declaration);
+    }
+}
+
+impl StmtVisitor<String> for JsonPrinter {
+    fn visit_block_stmt(&mut self, stmt: &BlockStmt) -> String {
+        return object(&[
+            ("kind", string("Block")),
+            ("line", stmt.line.to_string()),
+            ("statements", self.stmts(&stmt.stmts)),
+        ]);
+    }
+
+    fn visit_expression_stmt(&mut self, stmt: &ExpressionStmt) -> String {
+        return object(&[
+            ("kind", string("Expression")),
+            ("line", stmt.line.to_string()),
+            ("expression", self.expr(&stmt.expr)),
+        ]);
+    }
+
+    fn visit_print_stmt(&mut self, stmt: &PrintStmt) -> String {
+        return object(&[
+            ("kind", string("Print")),
+            ("line", stmt.line.to_string()),
+            ("expression", self.expr(&stmt.expr)),
+        ]);
+    }
+
+    fn visit_variable_stmt(&mut self, stmt: &VariableStmt) -> String {
+        return node(
+            "Var",
+            &stmt.name,
+            &[
+                ("name", string(&stmt.name.lexeme)),
+                ("initializer", self.optional_expr(&stmt.initializer)),
+            ],
+        );
+    }
+
+    fn visit_if_stmt(&mut self, stmt: &IfStmt) -> String {
+        return node(
+            "If",
+            &stmt.keyword,
+            &[
+                ("condition", self.expr(&stmt.condition)),
+                ("then", stmt.then_branch.accept(self)),
+                ("else", self.optional_stmt(&stmt.else_branch)),
+            ],
+        );
+    }
+
+    fn visit_while_stmt(&mut self, stmt: &WhileStmt) -> String {
+        return node(
+            "While",
+            &stmt.keyword,
+            &[
+                ("condition", self.expr(&stmt.condition)),
+                ("body", stmt.body.accept(self)),
+                ("increment", self.optional_stmt(&stmt.increment)),
+                ("else", self.optional_stmt(&stmt.else_branch)),
+            ],
+        );
+    }
+
+    fn visit_loop_stmt(&mut self, stmt: &LoopStmt) -> String {
+        return node("Loop", &stmt.keyword, &[("body", stmt.body.accept(self))]);
+    }
+
+    fn visit_repeat_stmt(&mut self, stmt: &RepeatStmt) -> String {
+        return node(
+            "Repeat",
+            &stmt.keyword,
+            &[
+                ("count", self.expr(&stmt.count)),
+                ("body", stmt.body.accept(self)),
+            ],
+        );
+    }
+
+    fn visit_for_in_stmt(&mut self, stmt: &ForInStmt) -> String {
+        return node(
+            "ForIn",
+            &stmt.keyword,
+            &[
+                ("name", string(&stmt.name.lexeme)),
+                ("iterable", self.expr(&stmt.iterable)),
+                ("body", stmt.body.accept(self)),
+                ("else", self.optional_stmt(&stmt.else_branch)),
+            ],
+        );
+    }
+
+    fn visit_function_stmt(&mut self, stmt: &FunctionStmt) -> String {
+        return self.function(stmt);
+    }
+
+    fn visit_return_stmt(&mut self, stmt: &ReturnStmt) -> String {
+        return node(
+            "Return",
+            &stmt.keyword,
+            &[("value", self.optional_expr(&stmt.value))],
+        );
+    }
+
+    fn visit_class_stmt(&mut self, stmt: &ClassStmt) -> String {
+        let superclass = match &stmt.superclass {
+            Some(superclass) => string(&superclass.name.lexeme),
+            None => "null".to_string(),
+        };
+
+        let fields = stmt
+            .fields
+            .iter()
+            .map(|field| self.visit_variable_stmt(field))
+            .collect();
+
+        let methods = stmt
+            .methods
+            .iter()
+            .map(|method| self.function(method))
+            .collect();
+
+        let static_methods = stmt
+            .static_methods
+            .iter()
+            .map(|method| self.function(method))
+            .collect();
+
+        return node(
+            "Class",
+            &stmt.name,
+            &[
+                ("name", string(&stmt.name.lexeme)),
+                ("superclass", superclass),
+                ("fields", array(fields)),
+                ("methods", array(methods)),
+                ("staticMethods", array(static_methods)),
+            ],
+        );
+    }
+
+    fn visit_break_stmt(&mut self, stmt: &BreakStmt) -> String {
+        return node(
+            "Break",
+            &stmt.keyword,
+            &[("value", self.optional_expr(&stmt.value))],
+        );
+    }
+
+    fn visit_continue_stmt(&mut self, stmt: &ContinueStmt) -> String {
+        return node("Continue", &stmt.keyword, &[]);
+    }
+
+    fn visit_import_stmt(&mut self, stmt: &ImportStmt) -> String {
+        return node("Import", &stmt.keyword, &[("path", string(&stmt.path))]);
+    }
+
+    fn visit_try_stmt(&mut self, stmt: &TryStmt) -> String {
+        return node(
+            "Try",
+            &stmt.keyword,
+            &[
+                ("body", self.stmts(&stmt.body)),
+                ("catchName", string(&stmt.catch_name.lexeme)),
+                ("catchBody", self.stmts(&stmt.catch_body)),
+            ],
+        );
+    }
+
+    fn visit_throw_stmt(&mut self, stmt: &ThrowStmt) -> String {
+        return node("Throw", &stmt.keyword, &[("value", self.expr(&stmt.value))]);
+    }
+
+    fn visit_debugger_stmt(&mut self, stmt: &DebuggerStmt) -> String {
+        return node("Debugger", &stmt.keyword, &[]);
+    }
+
+    fn visit_with_stmt(&mut self, stmt: &WithStmt) -> String {
+        return node(
+            "With",
+            &stmt.keyword,
+            &[
+                ("value", self.expr(&stmt.value)),
+                ("name", string(&stmt.name.lexeme)),
+                ("body", self.stmts(&stmt.body)),
+            ],
+        );
+    }
+}
+
+// A node starting at `token`, eg. `{"kind":"Var","line":1,...}`
+fn node(kind: &str, token: &Token, fields: &[(&str, String)]) -> String {
+    let mut all = vec![("kind", string(kind)), ("line", token.line.to_string())];
+    all.extend_from_slice(fields);
+
+    return object(&all);
+}
+
+fn object(fields: &[(&str, String)]) -> String {
+    let fields = fields
+        .iter()
+        .map(|(key, value)| format!("{}:{value}", string(key)))
+        .collect::<Vec<String>>();
+
+    return format!("{{{}}}", fields.join(","));
+}
+
+fn array(items: Vec<String>) -> String {
+    return format!("[{}]", items.join(","));
+}
+
+fn string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped.push('"');
+    return escaped;
+}
+
+// Re-lays out compact JSON, indenting two spaces per level. Empty objects and arrays stay
+// as `{}` and `[]`
+fn indent(json: &str) -> String {
+    let mut indented = String::with_capacity(json.len() * 2);
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut chars = json.chars().peekable();
+
+    let newline = |indented: &mut String, depth: usize| {
+        indented.push('\n');
+        indented.push_str(&"  ".repeat(depth));
+    };
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            indented.push(c);
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '"' => {
+                in_string = true;
+                indented.push(c);
+            }
+            '{' | '[' => {
+                indented.push(c);
+                if matches!(chars.peek(), Some('}' | ']')) {
+                    indented.push(chars.next().unwrap_or_default());
+                } else {
+                    depth += 1;
+                    newline(&mut indented, depth);
+                }
+            }
+            '}' | ']' => {
+                depth -= 1;
+                newline(&mut indented, depth);
+                indented.push(c);
+            }
+            ',' => {
+                indented.push(c);
+                newline(&mut indented, depth);
+            }
+            ':' => indented.push_str(": "),
+            c => indented.push(c),
+        }
+    }
+
+    return indented;
+}
@@ -1,6 +1,6 @@
-use super::expr::Expr;
+use super::expr::{Expr, ExprId, VariableExpr};
 
-use crate::token::Token;
+use crate::{string::LoxStr, token::Token};
 
 // Manually writing this part out
 // as it seems easier than translating the Java generation code
@@ -13,23 +13,39 @@ pub enum Stmt {
     Variable(VariableStmt),
     If(IfStmt),
     While(WhileStmt),
+    Loop(LoopStmt),
+    Repeat(RepeatStmt),
+    ForIn(ForInStmt),
     Function(FunctionStmt),
     Return(ReturnStmt),
     Class(ClassStmt),
+    Break(BreakStmt),
+    Continue(ContinueStmt),
+    Import(ImportStmt),
+    Try(TryStmt),
+    Throw(ThrowStmt),
+    With(WithStmt),
+    Debugger(DebuggerStmt),
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct BlockStmt {
+    // Of its `{`, or of the `for` it's desugared from
+    pub line: usize,
     pub stmts: Vec<Stmt>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ExpressionStmt {
+    // Of its first token, which the expression's own line may not be, eg. for a call split
+    // across lines
+    pub line: usize,
     pub expr: Expr,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct PrintStmt {
+    pub line: usize,
     pub expr: Expr,
 }
 
@@ -41,6 +57,7 @@ pub struct VariableStmt {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct IfStmt {
+    pub keyword: Token,
     pub condition: Expr,
     pub then_branch: Box<Stmt>,
     pub else_branch: Option<Box<Stmt>>,
@@ -48,14 +65,47 @@ pub struct IfStmt {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct WhileStmt {
+    pub keyword: Token,
     pub condition: Expr,
     pub body: Box<Stmt>,
+    // A `for` loop's increment, as an expression statement. It's kept out of the body so a
+    // `continue` still runs it
+    pub increment: Option<Box<Stmt>>,
+    // Runs once the loop finishes, unless it was left with a `break`
+    pub else_branch: Option<Box<Stmt>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoopStmt {
+    pub keyword: Token,
+    pub body: Box<Stmt>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepeatStmt {
+    pub keyword: Token,
+    pub count: Expr,
+    pub body: Box<Stmt>,
+}
+
+// `for (name in iterable) body`, binding each element of a list, or each key of a map
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForInStmt {
+    pub keyword: Token,
+    pub name: Token,
+    pub iterable: Expr,
+    pub body: Box<Stmt>,
+    pub else_branch: Option<Box<Stmt>>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct FunctionStmt {
+    // From the same counter as expressions, to key what the resolver finds out about it
+    pub id: ExprId,
     pub name: Token,
     pub params: Vec<Token>,
+    // Lines up with `params`. Only trailing parameters can have defaults
+    pub defaults: Vec<Option<Expr>>,
     pub body: Vec<Stmt>,
 }
 
@@ -65,10 +115,87 @@ pub struct ReturnStmt {
     pub value: Option<Expr>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct BreakStmt {
+    pub keyword: Token,
+    pub value: Option<Expr>,
+}
+
+// Skips the rest of the loop's body, on to its next iteration
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContinueStmt {
+    pub keyword: Token,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportStmt {
+    pub keyword: Token,
+    pub path: LoxStr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TryStmt {
+    pub keyword: Token,
+    pub body: Vec<Stmt>,
+    pub catch_name: Token,
+    pub catch_body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThrowStmt {
+    pub keyword: Token,
+    pub value: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WithStmt {
+    pub keyword: Token,
+    pub value: Expr,
+    pub name: Token,
+    pub body: Vec<Stmt>,
+}
+
+// `debugger;`, handing the current scope to the interpreter's breakpoint hook if it has one
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebuggerStmt {
+    pub keyword: Token,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct ClassStmt {
     pub name: Token,
+    pub superclass: Option<VariableExpr>,
+    pub fields: Vec<VariableStmt>,
     pub methods: Vec<FunctionStmt>,
+    // Declared with a leading `class`, eg. `class create() {...}`, and called on the class itself
+    pub static_methods: Vec<FunctionStmt>,
+}
+
+impl Stmt {
+    // Where it starts, if it has a token to tell. Blocks don't
+    pub fn line(&self) -> Option<usize> {
+        return match self {
+            Self::Block(_) => None,
+            Self::Expression(stmt) => Some(stmt.line),
+            Self::Print(stmt) => Some(stmt.line),
+            Self::Variable(stmt) => Some(stmt.name.line),
+            Self::If(stmt) => Some(stmt.keyword.line),
+            Self::While(stmt) => Some(stmt.keyword.line),
+            Self::Loop(stmt) => Some(stmt.keyword.line),
+            Self::Repeat(stmt) => Some(stmt.keyword.line),
+            Self::ForIn(stmt) => Some(stmt.keyword.line),
+            Self::Function(stmt) => Some(stmt.name.line),
+            Self::Return(stmt) => Some(stmt.keyword.line),
+            Self::Class(stmt) => Some(stmt.name.line),
+            Self::Break(stmt) => Some(stmt.keyword.line),
+            Self::Continue(stmt) => Some(stmt.keyword.line),
+            Self::Import(stmt) => Some(stmt.keyword.line),
+            Self::Try(stmt) => Some(stmt.keyword.line),
+            Self::Throw(stmt) => Some(stmt.keyword.line),
+            Self::With(stmt) => Some(stmt.keyword.line),
+            Self::Debugger(stmt) => Some(stmt.keyword.line),
+        };
+    }
 }
 
 // Visitor pattern
@@ -79,9 +206,19 @@ pub trait StmtVisitor<R> {
     fn visit_variable_stmt(&mut self, stmt: &VariableStmt) -> R;
     fn visit_if_stmt(&mut self, stmt: &IfStmt) -> R;
     fn visit_while_stmt(&mut self, stmt: &WhileStmt) -> R;
+    fn visit_loop_stmt(&mut self, stmt: &LoopStmt) -> R;
+    fn visit_repeat_stmt(&mut self, stmt: &RepeatStmt) -> R;
+    fn visit_for_in_stmt(&mut self, stmt: &ForInStmt) -> R;
     fn visit_function_stmt(&mut self, stmt: &FunctionStmt) -> R;
     fn visit_return_stmt(&mut self, stmt: &ReturnStmt) -> R;
     fn visit_class_stmt(&mut self, stmt: &ClassStmt) -> R;
+    fn visit_break_stmt(&mut self, stmt: &BreakStmt) -> R;
+    fn visit_continue_stmt(&mut self, stmt: &ContinueStmt) -> R;
+    fn visit_import_stmt(&mut self, stmt: &ImportStmt) -> R;
+    fn visit_try_stmt(&mut self, stmt: &TryStmt) -> R;
+    fn visit_throw_stmt(&mut self, stmt: &ThrowStmt) -> R;
+    fn visit_with_stmt(&mut self, stmt: &WithStmt) -> R;
+    fn visit_debugger_stmt(&mut self, stmt: &DebuggerStmt) -> R;
 }
 
 pub trait StmtAccept<R, V: StmtVisitor<R>> {
@@ -97,9 +234,19 @@ impl<R, V: StmtVisitor<R>> StmtAccept<R, V> for Stmt {
             Self::Variable(stmt) => stmt.accept(visitor),
             Self::If(stmt) => stmt.accept(visitor),
             Self::While(stmt) => stmt.accept(visitor),
+            Self::Loop(stmt) => stmt.accept(visitor),
+            Self::Repeat(stmt) => stmt.accept(visitor),
+            Self::ForIn(stmt) => stmt.accept(visitor),
             Self::Function(stmt) => stmt.accept(visitor),
             Self::Return(stmt) => stmt.accept(visitor),
             Self::Class(stmt) => stmt.accept(visitor),
+            Self::Break(stmt) => stmt.accept(visitor),
+            Self::Continue(stmt) => stmt.accept(visitor),
+            Self::Import(stmt) => stmt.accept(visitor),
+            Self::Try(stmt) => stmt.accept(visitor),
+            Self::Throw(stmt) => stmt.accept(visitor),
+            Self::With(stmt) => stmt.accept(visitor),
+            Self::Debugger(stmt) => stmt.accept(visitor),
         };
     }
 }
@@ -140,6 +287,24 @@ impl<R, V: StmtVisitor<R>> StmtAccept<R, V> for WhileStmt {
     }
 }
 
+impl<R, V: StmtVisitor<R>> StmtAccept<R, V> for LoopStmt {
+    fn accept(&self, visitor: &mut V) -> R {
+        return visitor.visit_loop_stmt(self);
+    }
+}
+
+impl<R, V: StmtVisitor<R>> StmtAccept<R, V> for RepeatStmt {
+    fn accept(&self, visitor: &mut V) -> R {
+        return visitor.visit_repeat_stmt(self);
+    }
+}
+
+impl<R, V: StmtVisitor<R>> StmtAccept<R, V> for ForInStmt {
+    fn accept(&self, visitor: &mut V) -> R {
+        return visitor.visit_for_in_stmt(self);
+    }
+}
+
 impl<R, V: StmtVisitor<R>> StmtAccept<R, V> for FunctionStmt {
     fn accept(&self, visitor: &mut V) -> R {
         return visitor.visit_function_stmt(self);
@@ -157,3 +322,45 @@ impl<R, V: StmtVisitor<R>> StmtAccept<R, V> for ClassStmt {
         return visitor.visit_class_stmt(self);
     }
 }
+
+impl<R, V: StmtVisitor<R>> StmtAccept<R, V> for BreakStmt {
+    fn accept(&self, visitor: &mut V) -> R {
+        return visitor.visit_break_stmt(self);
+    }
+}
+
+impl<R, V: StmtVisitor<R>> StmtAccept<R, V> for ContinueStmt {
+    fn accept(&self, visitor: &mut V) -> R {
+        return visitor.visit_continue_stmt(self);
+    }
+}
+
+impl<R, V: StmtVisitor<R>> StmtAccept<R, V> for ImportStmt {
+    fn accept(&self, visitor: &mut V) -> R {
+        return visitor.visit_import_stmt(self);
+    }
+}
+
+impl<R, V: StmtVisitor<R>> StmtAccept<R, V> for TryStmt {
+    fn accept(&self, visitor: &mut V) -> R {
+        return visitor.visit_try_stmt(self);
+    }
+}
+
+impl<R, V: StmtVisitor<R>> StmtAccept<R, V> for ThrowStmt {
+    fn accept(&self, visitor: &mut V) -> R {
+        return visitor.visit_throw_stmt(self);
+    }
+}
+
+impl<R, V: StmtVisitor<R>> StmtAccept<R, V> for WithStmt {
+    fn accept(&self, visitor: &mut V) -> R {
+        return visitor.visit_with_stmt(self);
+    }
+}
+
+impl<R, V: StmtVisitor<R>> StmtAccept<R, V> for DebuggerStmt {
+    fn accept(&self, visitor: &mut V) -> R {
+        return visitor.visit_debugger_stmt(self);
+    }
+}
@@ -0,0 +1,49 @@
+// What a script is allowed to do, for running untrusted code. Natives that aren't allowed
+// are never defined, so a script using one fails as if it didn't exist, and the limits
+// stop it with a runtime error rather than letting it hang or use up memory or the stack.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capabilities {
+    // `readFile` / `writeFile`, and `import`
+    pub filesystem: bool,
+    // `clock`, `clockMillis` and `clockNanos`, which could be used for timing attacks
+    pub clock: bool,
+
+    // Loop bodies across the whole run
+    pub max_loop_iterations: Option<usize>,
+    // Calls in progress at once, eg. how deep recursion goes
+    pub max_call_depth: Option<usize>,
+    // In characters
+    pub max_string_length: Option<usize>,
+    pub max_list_length: Option<usize>,
+}
+
+impl Capabilities {
+    // Everything, with no limits
+    pub fn all() -> Self {
+        return Self {
+            filesystem: true,
+            clock: true,
+            max_loop_iterations: None,
+            max_call_depth: None,
+            max_string_length: None,
+            max_list_length: None,
+        };
+    }
+
+    pub fn sandboxed() -> Self {
+        return Self {
+            filesystem: false,
+            clock: false,
+            max_loop_iterations: Some(1_000_000),
+            max_call_depth: Some(500),
+            max_string_length: Some(1_000_000),
+            max_list_length: Some(100_000),
+        };
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        return Self::all();
+    }
+}
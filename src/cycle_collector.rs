@@ -0,0 +1,333 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::{hash_map, HashMap, HashSet},
+    rc::{Rc, Weak},
+};
+
+use crate::{
+    environment::Environment, lox_callable::LoxCallable, lox_class::LoxClass,
+    lox_function::LoxFunction, lox_map::LoxMap, ordered_map::OrderedMap,
+    runtime_value::RuntimeValue, string::LoxStr,
+};
+
+// A function keeps its closure alive, and the closure keeps alive whatever functions were
+// declared in it, so a function declared inside another is a reference cycle `Rc` never
+// frees, eg. every call to
+//   fun makeCounter() { var count = 0; fun increment() { ... } return increment; }
+// `Weak` back-edges can't fix that, as the function returned may be the only thing left
+// holding the environment. Instead every closure is tracked here, and `collect` empties
+// those only alive because of cycles, which frees them.
+//
+// It's trial deletion, like CPython's collector: following every reference between the
+// tracked environments and what they hold counts how many of each one's `Rc`s come from
+// inside that graph. Anything with more is held from elsewhere, eg. by the interpreter or a
+// Rust local, and it and what it reaches stay. The rest can only be reached through cycles.
+// A reference it doesn't know how to follow, like a native's captures, can only make
+// something look held from elsewhere, so it errs towards keeping things.
+//
+// Cycles made only of lists, maps and instances, without a closure, aren't found.
+
+thread_local! {
+    static TRACKED: RefCell<Vec<Weak<RefCell<Environment>>>> = const { RefCell::new(vec![]) };
+
+    // Tracked since the last collection, and how many to wait for before the next
+    static SINCE_COLLECTION: Cell<usize> = const { Cell::new(0) };
+    static COLLECT_AFTER: Cell<usize> = const { Cell::new(MIN_COLLECT_AFTER) };
+
+    // When to drop the dead entries from `TRACKED`, which otherwise keep their allocations
+    static PRUNE_AT: Cell<usize> = const { Cell::new(MIN_COLLECT_AFTER) };
+}
+
+const MIN_COLLECT_AFTER: usize = 10_000;
+
+pub fn track(closure: &Rc<RefCell<Environment>>) {
+    TRACKED.with(|tracked| {
+        let mut tracked = tracked.borrow_mut();
+
+        if tracked.len() >= PRUNE_AT.get() {
+            tracked.retain(|environment| environment.strong_count() > 0);
+            PRUNE_AT.set(MIN_COLLECT_AFTER.max(tracked.len() * 2));
+        }
+
+        tracked.push(Rc::downgrade(closure));
+    });
+
+    SINCE_COLLECTION.set(SINCE_COLLECTION.get() + 1);
+}
+
+// Collects once enough closures have been made since the last time that it's worth it,
+// so the cost grows with how much is alive rather than with how often it's called
+pub fn collect_if_due() {
+    if SINCE_COLLECTION.get() >= COLLECT_AFTER.get() {
+        collect();
+    }
+}
+
+// Returns how many environments it freed
+pub fn collect() -> usize {
+    let tracked = TRACKED.with(|tracked| {
+        let mut tracked = tracked.borrow_mut();
+        tracked.retain(|environment| environment.strong_count() > 0);
+
+        return tracked
+            .iter()
+            .filter_map(|environment| environment.upgrade())
+            .collect::<Vec<_>>();
+    });
+
+    let mut graph = Graph::default();
+    for environment in tracked {
+        graph.add(Node::Environment(environment));
+    }
+    graph.follow_references();
+
+    let garbage = graph.garbage();
+    let freed = garbage
+        .iter()
+        .filter(|node| matches!(node, Node::Environment(_)))
+        .count();
+
+    for node in &garbage {
+        node.clear();
+    }
+
+    drop(garbage);
+    drop(graph);
+
+    let alive = TRACKED.with(|tracked| {
+        let mut tracked = tracked.borrow_mut();
+        tracked.retain(|environment| environment.strong_count() > 0);
+        return tracked.len();
+    });
+
+    SINCE_COLLECTION.set(0);
+    COLLECT_AFTER.set(MIN_COLLECT_AFTER.max(alive));
+
+    return freed;
+}
+
+// Something shared that holds runtime values, and so can be part of a cycle
+#[derive(Clone)]
+enum Node {
+    Environment(Rc<RefCell<Environment>>),
+    List(Rc<RefCell<Vec<RuntimeValue>>>),
+    Map(Rc<RefCell<LoxMap>>),
+    Fields(Rc<RefCell<OrderedMap<LoxStr, RuntimeValue>>>),
+    Methods(Rc<RefCell<OrderedMap<LoxStr, LoxFunction>>>),
+    StaticMethods(Rc<OrderedMap<LoxStr, LoxFunction>>),
+    Superclass(Rc<LoxClass>),
+}
+
+impl Node {
+    fn id(&self) -> *const () {
+        return match self {
+            Self::Environment(rc) => Rc::as_ptr(rc) as *const (),
+            Self::List(rc) => Rc::as_ptr(rc) as *const (),
+            Self::Map(rc) => Rc::as_ptr(rc) as *const (),
+            Self::Fields(rc) => Rc::as_ptr(rc) as *const (),
+            Self::Methods(rc) => Rc::as_ptr(rc) as *const (),
+            Self::StaticMethods(rc) => Rc::as_ptr(rc) as *const (),
+            Self::Superclass(rc) => Rc::as_ptr(rc) as *const (),
+        };
+    }
+
+    fn strong_count(&self) -> usize {
+        return match self {
+            Self::Environment(rc) => Rc::strong_count(rc),
+            Self::List(rc) => Rc::strong_count(rc),
+            Self::Map(rc) => Rc::strong_count(rc),
+            Self::Fields(rc) => Rc::strong_count(rc),
+            Self::Methods(rc) => Rc::strong_count(rc),
+            Self::StaticMethods(rc) => Rc::strong_count(rc),
+            Self::Superclass(rc) => Rc::strong_count(rc),
+        };
+    }
+
+    // One entry per `Rc` this holds. `None` if it's borrowed, so can't be looked into
+    fn references(&self) -> Option<Vec<Node>> {
+        let mut references = vec![];
+
+        match self {
+            Self::Environment(environment) => {
+                let environment = environment.try_borrow().ok()?;
+
+                if let Some(enclosing) = environment.enclosing() {
+                    references.push(Self::Environment(Rc::clone(enclosing)));
+                }
+
+                for value in environment.values() {
+                    value_references(value, &mut references);
+                }
+            }
+            Self::List(values) => {
+                for value in values.try_borrow().ok()?.iter() {
+                    value_references(value, &mut references);
+                }
+            }
+            Self::Map(map) => {
+                for value in map.try_borrow().ok()?.values() {
+                    value_references(&value, &mut references);
+                }
+            }
+            Self::Fields(fields) => {
+                for (_, value) in fields.try_borrow().ok()?.iter() {
+                    value_references(value, &mut references);
+                }
+            }
+            Self::Methods(methods) => {
+                for (_, method) in methods.try_borrow().ok()?.iter() {
+                    references.push(Self::Environment(Rc::clone(&method.closure)));
+                }
+            }
+            Self::StaticMethods(methods) => {
+                for (_, method) in methods.iter() {
+                    references.push(Self::Environment(Rc::clone(&method.closure)));
+                }
+            }
+            Self::Superclass(class) => class_references(class, &mut references),
+        }
+
+        return Some(references);
+    }
+
+    // Drops what this holds, breaking the cycles through it. Static methods and
+    // superclasses can't be changed, but the cycles through them also go through a closure
+    fn clear(&self) {
+        match self {
+            Self::Environment(environment) => {
+                if let Ok(mut environment) = environment.try_borrow_mut() {
+                    *environment = Environment::new();
+                }
+            }
+            Self::List(values) => {
+                if let Ok(mut values) = values.try_borrow_mut() {
+                    values.clear();
+                }
+            }
+            Self::Map(map) => {
+                if let Ok(mut map) = map.try_borrow_mut() {
+                    *map = LoxMap::new();
+                }
+            }
+            Self::Fields(fields) => {
+                if let Ok(mut fields) = fields.try_borrow_mut() {
+                    *fields = OrderedMap::new();
+                }
+            }
+            Self::Methods(methods) => {
+                if let Ok(mut methods) = methods.try_borrow_mut() {
+                    *methods = OrderedMap::new();
+                }
+            }
+            Self::StaticMethods(_) | Self::Superclass(_) => {}
+        }
+    }
+}
+
+fn value_references(value: &RuntimeValue, references: &mut Vec<Node>) {
+    match value {
+        RuntimeValue::List(values) => references.push(Node::List(Rc::clone(values))),
+        RuntimeValue::Map(map) => references.push(Node::Map(Rc::clone(map))),
+        RuntimeValue::LoxInstance(instance) => {
+            class_references(&instance.class, references);
+            references.push(Node::Fields(Rc::clone(&instance.fields)));
+        }
+        RuntimeValue::LoxCallable(LoxCallable::LoxFunction(function)) => {
+            references.push(Node::Environment(Rc::clone(&function.closure)));
+        }
+        RuntimeValue::LoxCallable(LoxCallable::LoxClass(class)) => {
+            class_references(class, references);
+        }
+        RuntimeValue::LoxCallable(LoxCallable::PrimitiveMethod(method)) => {
+            value_references(method.receiver(), references);
+        }
+        _ => {}
+    }
+}
+
+fn class_references(class: &LoxClass, references: &mut Vec<Node>) {
+    references.push(Node::Methods(Rc::clone(&class.methods)));
+    references.push(Node::StaticMethods(Rc::clone(&class.static_methods)));
+    references.push(Node::Environment(Rc::clone(class.closure())));
+
+    if let Some(superclass) = &class.superclass {
+        references.push(Node::Superclass(Rc::clone(superclass)));
+    }
+}
+
+#[derive(Default)]
+struct Graph {
+    nodes: HashMap<*const (), Visited>,
+    // Added but not yet followed
+    pending: Vec<*const ()>,
+}
+
+struct Visited {
+    node: Node,
+    // How many of its `Rc`s are held by other nodes
+    internal: usize,
+    references: Vec<*const ()>,
+    // Couldn't be looked into, so has to be assumed to be held from elsewhere
+    pinned: bool,
+}
+
+impl Graph {
+    fn add(&mut self, node: Node) -> *const () {
+        let id = node.id();
+
+        if let hash_map::Entry::Vacant(entry) = self.nodes.entry(id) {
+            entry.insert(Visited {
+                node,
+                internal: 0,
+                references: vec![],
+                pinned: false,
+            });
+            self.pending.push(id);
+        }
+
+        return id;
+    }
+
+    fn follow_references(&mut self) {
+        while let Some(id) = self.pending.pop() {
+            let Some(references) = self.nodes[&id].node.references() else {
+                self.nodes.get_mut(&id).unwrap().pinned = true;
+                continue;
+            };
+
+            let mut ids = vec![];
+            for reference in references {
+                let reference = self.add(reference);
+                self.nodes.get_mut(&reference).unwrap().internal += 1;
+                ids.push(reference);
+            }
+
+            self.nodes.get_mut(&id).unwrap().references = ids;
+        }
+    }
+
+    // The nodes nothing outside the graph can reach
+    fn garbage(&self) -> Vec<Node> {
+        // Each node's own entry holds one of its `Rc`s
+        let mut reachable = self
+            .nodes
+            .iter()
+            .filter(|(_, entry)| entry.pinned || entry.node.strong_count() > entry.internal + 1)
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+
+        let mut alive = HashSet::new();
+        while let Some(id) = reachable.pop() {
+            if alive.insert(id) {
+                reachable.extend(&self.nodes[&id].references);
+            }
+        }
+
+        return self
+            .nodes
+            .iter()
+            .filter(|(id, _)| !alive.contains(*id))
+            .map(|(_, entry)| entry.node.clone())
+            .collect();
+    }
+}
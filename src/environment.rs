@@ -27,20 +27,73 @@ impl Environment {
         };
     }
 
+    pub fn enclosing(&self) -> Option<&Rc<RefCell<Environment>>> {
+        return self.enclosing.as_ref();
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &RuntimeValue> {
+        return self.values.values();
+    }
+
     pub fn define(&mut self, name: LoxStr, value: RuntimeValue) {
         self.values.insert(name, value);
     }
 
+    // Like `define`, but refuses to replace an existing binding
+    pub fn define_new(&mut self, name: &Token, value: RuntimeValue) -> RuntimeResult<()> {
+        if self.values.contains_key(&name.lexeme) {
+            return Err(RuntimeError::AlreadyDefined {
+                name: name.lexeme.clone(),
+                line: name.line,
+            });
+        }
+
+        self.values.insert(name.lexeme.clone(), value);
+        return Ok(());
+    }
+
+    // This scope's own bindings, sorted by name
+    pub fn entries(&self) -> Vec<(LoxStr, RuntimeValue)> {
+        let mut entries = self
+            .values
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect::<Vec<_>>();
+
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        return entries;
+    }
+
+    // Every binding visible from this scope, leaving out those shadowed by an inner scope
+    pub fn all_entries(&self) -> Vec<(LoxStr, RuntimeValue)> {
+        let mut entries = self.entries();
+
+        if let Some(enclosing) = &self.enclosing {
+            for (name, value) in enclosing.borrow().all_entries() {
+                if !entries.iter().any(|(existing, _)| *existing == name) {
+                    entries.push((name, value));
+                }
+            }
+        }
+
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        return entries;
+    }
+
+    // Looks in this scope alone, by name rather than token
+    pub fn get_own(&self, name: &LoxStr) -> Option<RuntimeValue> {
+        return self.values.get(name).cloned();
+    }
+
     pub fn get_at(this: Rc<RefCell<Self>>, distance: usize, name: &Token) -> RuntimeResult {
         return Self::ancestor(this, distance)
             .borrow()
             .values
             .get(&name.lexeme)
             .cloned()
-            .ok_or_else(|| RuntimeError::UndefinedVariable {
-                name: name.clone(),
-                details: None,
-            });
+            .ok_or_else(|| RuntimeError::UndefinedVariable { name: name.clone() });
     }
 
     pub fn get(&self, name: &Token) -> RuntimeResult {
@@ -52,10 +105,7 @@ impl Environment {
             return enclosing.borrow().get(name);
         }
 
-        return Err(RuntimeError::UndefinedVariable {
-            name: name.clone(),
-            details: None,
-        });
+        return Err(RuntimeError::UndefinedVariable { name: name.clone() });
     }
 
     pub fn assign_at(
@@ -71,15 +121,12 @@ impl Environment {
             return Ok(());
         }
 
-        return Err(RuntimeError::UndefinedVariable {
-            name: name.clone(),
-            details: Some(format!("Cannot assign [{value:?}] to undefined variable")),
-        });
+        return Err(RuntimeError::UndefinedVariable { name: name.clone() });
     }
 
     pub fn assign(&mut self, name: Token, value: RuntimeValue) -> RuntimeResult<()> {
-        if self.values.contains_key(&name.lexeme) {
-            self.values.insert(name.lexeme, value);
+        if let Some(existing) = self.values.get_mut(&name.lexeme) {
+            *existing = value;
             return Ok(());
         }
 
@@ -87,10 +134,7 @@ impl Environment {
             return enclosing.borrow_mut().assign(name, value);
         }
 
-        return Err(RuntimeError::UndefinedVariable {
-            name: name.clone(),
-            details: Some(format!("Cannot assign [{value:?}] to undefined variable")),
-        });
+        return Err(RuntimeError::UndefinedVariable { name: name.clone() });
     }
 
     fn ancestor(this: Rc<RefCell<Self>>, distance: usize) -> Rc<RefCell<Self>> {
@@ -1,32 +1,271 @@
 use crate::{
     ast::{expr::*, stmt::*},
+    capabilities::Capabilities,
+    cycle_collector,
     environment::Environment,
     lox,
-    lox_callable::{Clock, LoxCall, LoxCallable},
+    lox_callable::{
+        self, Apply, ApproxEqual, AssertType, Bin, CallByName, Captures, Clock, ClockMillis,
+        ClockNanos, Contains, Defined, DeleteField, Fields, FilterList, FloorDiv, Freeze, Globals,
+        Hex, Join, Keys, LoxCall, LoxCallable, MapGet, MapList, MapSet, Math, Methods, NativeFn,
+        NewMap, PrimitiveMethod, PrintErr, PrintWith, RandomInt, RandomNumber, Range, ReadFile,
+        Seed, ShallowCopy, Split, ToBool, ToFloat, ToInt, ToLower, ToUpper, Trim, Values,
+        WriteFile,
+    },
     lox_class::LoxClass,
     lox_function::LoxFunction,
-    runtime_value::{RuntimeError, RuntimeResult, RuntimeValue},
+    optimizer::Optimizer,
+    ordered_map::OrderedMap,
+    random::Random,
+    runtime_value::{
+        whole_number, ControlFlow, ControlFlowResult, RuntimeError, RuntimeResult, RuntimeValue,
+    },
     string::LoxStr,
     token::Token,
     token_type::TokenType,
 };
 
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    rc::Rc,
+    time::Instant,
+};
+
+// Called by `debugger;` with the scope it's in, for embedders to inspect
+pub type BreakpointHook = Box<dyn FnMut(&Interpreter, &Environment)>;
 
 pub struct Interpreter {
     pub globals: Rc<RefCell<Environment>>,
 
     environment: Rc<RefCell<Environment>>,
     locals: HashMap<ExprId, usize>,
+    // The local variables from outside each function that it uses, by `FunctionStmt::id`
+    captures: HashMap<ExprId, Vec<LoxStr>>,
+    output: Box<dyn io::Write>,
+    // Where `printErr` writes
+    err_output: Box<dyn io::Write>,
+    use_resolver: bool,
+    capabilities: Capabilities,
+    loop_iterations: usize,
+    call_depth: usize,
+    // The line of the statement running, for errors without their own. A call puts the
+    // caller's back once it returns
+    current_line: Option<usize>,
+    strict_conditions: bool,
+    strict_arity: bool,
+    strict_globals: bool,
+    strict_init: bool,
+    boolean_logical: bool,
+    // Fold constant expressions between resolving and running
+    optimize: bool,
+    // Without one, `debugger;` does nothing
+    breakpoint_hook: Option<BreakpointHook>,
+    // Where `--trace` logs each statement and value, if it's on
+    trace_output: Option<Box<dyn io::Write>>,
+    pub random: Random,
+    // What `clockMillis` / `clockNanos` count from
+    pub start: Instant,
+    // Files currently running, innermost last, for relative imports and catching cycles
+    script_paths: Vec<PathBuf>,
+    imported: HashSet<PathBuf>,
 }
 
 impl Interpreter {
-    pub fn new() -> Self {
+    // Lets callers capture what the program prints, eg. the test runner
+    pub fn with_capabilities(output: Box<dyn io::Write>, capabilities: Capabilities) -> Self {
         let globals = Rc::new(RefCell::new(Environment::new()));
 
+        if capabilities.clock {
+            globals.borrow_mut().define(
+                "clock".into(),
+                RuntimeValue::LoxCallable(LoxCallable::Clock(Clock)),
+            );
+            globals.borrow_mut().define(
+                "clockMillis".into(),
+                RuntimeValue::LoxCallable(LoxCallable::ClockMillis(ClockMillis)),
+            );
+            globals.borrow_mut().define(
+                "clockNanos".into(),
+                RuntimeValue::LoxCallable(LoxCallable::ClockNanos(ClockNanos)),
+            );
+        }
+        globals.borrow_mut().define(
+            "deleteField".into(),
+            RuntimeValue::LoxCallable(LoxCallable::DeleteField(DeleteField)),
+        );
+        globals.borrow_mut().define(
+            "copy".into(),
+            RuntimeValue::LoxCallable(LoxCallable::ShallowCopy(ShallowCopy)),
+        );
+        globals.borrow_mut().define(
+            "floorDiv".into(),
+            RuntimeValue::LoxCallable(LoxCallable::FloorDiv(FloorDiv)),
+        );
+        globals.borrow_mut().define(
+            "approxEqual".into(),
+            RuntimeValue::LoxCallable(LoxCallable::ApproxEqual(ApproxEqual)),
+        );
+        for (name, value) in CONSTANTS {
+            globals
+                .borrow_mut()
+                .define(name.into(), RuntimeValue::Number(value));
+        }
+        for math in Math::ALL {
+            globals.borrow_mut().define(
+                math.name().into(),
+                RuntimeValue::LoxCallable(LoxCallable::Math(math)),
+            );
+        }
+        globals.borrow_mut().define(
+            "write".into(),
+            // Qualified since `Write` is also the io trait
+            RuntimeValue::LoxCallable(LoxCallable::Write(lox_callable::Write)),
+        );
+        globals.borrow_mut().define(
+            "printErr".into(),
+            RuntimeValue::LoxCallable(LoxCallable::PrintErr(PrintErr)),
+        );
+        globals.borrow_mut().define(
+            "fields".into(),
+            RuntimeValue::LoxCallable(LoxCallable::Fields(Fields)),
+        );
+        globals.borrow_mut().define(
+            "methods".into(),
+            RuntimeValue::LoxCallable(LoxCallable::Methods(Methods)),
+        );
+        globals.borrow_mut().define(
+            "assertType".into(),
+            RuntimeValue::LoxCallable(LoxCallable::AssertType(AssertType)),
+        );
+        globals.borrow_mut().define(
+            "random".into(),
+            RuntimeValue::LoxCallable(LoxCallable::RandomNumber(RandomNumber)),
+        );
+        globals.borrow_mut().define(
+            "randomInt".into(),
+            RuntimeValue::LoxCallable(LoxCallable::RandomInt(RandomInt)),
+        );
+        globals.borrow_mut().define(
+            "seed".into(),
+            RuntimeValue::LoxCallable(LoxCallable::Seed(Seed)),
+        );
+        globals.borrow_mut().define(
+            "toUpper".into(),
+            RuntimeValue::LoxCallable(LoxCallable::ToUpper(ToUpper)),
+        );
+        globals.borrow_mut().define(
+            "toLower".into(),
+            RuntimeValue::LoxCallable(LoxCallable::ToLower(ToLower)),
+        );
+        globals.borrow_mut().define(
+            "trim".into(),
+            RuntimeValue::LoxCallable(LoxCallable::Trim(Trim)),
+        );
+        globals.borrow_mut().define(
+            "contains".into(),
+            RuntimeValue::LoxCallable(LoxCallable::Contains(Contains)),
+        );
+        globals.borrow_mut().define(
+            "split".into(),
+            RuntimeValue::LoxCallable(LoxCallable::Split(Split)),
+        );
+        globals.borrow_mut().define(
+            "map".into(),
+            RuntimeValue::LoxCallable(LoxCallable::NewMap(NewMap)),
+        );
+        globals.borrow_mut().define(
+            "mapGet".into(),
+            RuntimeValue::LoxCallable(LoxCallable::MapGet(MapGet)),
+        );
+        globals.borrow_mut().define(
+            "mapSet".into(),
+            RuntimeValue::LoxCallable(LoxCallable::MapSet(MapSet)),
+        );
+        globals.borrow_mut().define(
+            "keys".into(),
+            RuntimeValue::LoxCallable(LoxCallable::Keys(Keys)),
+        );
+        globals.borrow_mut().define(
+            "values".into(),
+            RuntimeValue::LoxCallable(LoxCallable::Values(Values)),
+        );
+        globals.borrow_mut().define(
+            "freeze".into(),
+            RuntimeValue::LoxCallable(LoxCallable::Freeze(Freeze)),
+        );
+        globals.borrow_mut().define(
+            "hex".into(),
+            RuntimeValue::LoxCallable(LoxCallable::Hex(Hex)),
+        );
+        globals.borrow_mut().define(
+            "bin".into(),
+            RuntimeValue::LoxCallable(LoxCallable::Bin(Bin)),
+        );
+        globals.borrow_mut().define(
+            "apply".into(),
+            RuntimeValue::LoxCallable(LoxCallable::Apply(Apply)),
+        );
+        globals.borrow_mut().define(
+            "call".into(),
+            RuntimeValue::LoxCallable(LoxCallable::CallByName(CallByName)),
+        );
+        globals.borrow_mut().define(
+            "defined".into(),
+            RuntimeValue::LoxCallable(LoxCallable::Defined(Defined)),
+        );
+        globals.borrow_mut().define(
+            "globals".into(),
+            RuntimeValue::LoxCallable(LoxCallable::Globals(Globals)),
+        );
+        globals.borrow_mut().define(
+            "range".into(),
+            RuntimeValue::LoxCallable(LoxCallable::Range(Range)),
+        );
+        globals.borrow_mut().define(
+            "bool".into(),
+            RuntimeValue::LoxCallable(LoxCallable::ToBool(ToBool)),
+        );
+        globals.borrow_mut().define(
+            "int".into(),
+            RuntimeValue::LoxCallable(LoxCallable::ToInt(ToInt)),
+        );
         globals.borrow_mut().define(
-            "clock".into(),
-            RuntimeValue::LoxCallable(LoxCallable::Clock(Clock)),
+            "float".into(),
+            RuntimeValue::LoxCallable(LoxCallable::ToFloat(ToFloat)),
+        );
+        if capabilities.filesystem {
+            globals.borrow_mut().define(
+                "readFile".into(),
+                RuntimeValue::LoxCallable(LoxCallable::ReadFile(ReadFile)),
+            );
+            globals.borrow_mut().define(
+                "writeFile".into(),
+                RuntimeValue::LoxCallable(LoxCallable::WriteFile(WriteFile)),
+            );
+        }
+        globals.borrow_mut().define(
+            "printWith".into(),
+            RuntimeValue::LoxCallable(LoxCallable::PrintWith(PrintWith)),
+        );
+        globals.borrow_mut().define(
+            "captures".into(),
+            RuntimeValue::LoxCallable(LoxCallable::Captures(Captures)),
+        );
+        globals.borrow_mut().define(
+            "join".into(),
+            RuntimeValue::LoxCallable(LoxCallable::Join(Join)),
+        );
+        globals.borrow_mut().define(
+            "mapList".into(),
+            RuntimeValue::LoxCallable(LoxCallable::MapList(MapList)),
+        );
+        globals.borrow_mut().define(
+            "filterList".into(),
+            RuntimeValue::LoxCallable(LoxCallable::FilterList(FilterList)),
         );
 
         return Self {
@@ -34,21 +273,117 @@ impl Interpreter {
             globals,
 
             locals: HashMap::new(),
+            captures: HashMap::new(),
+            output,
+            err_output: Box::new(io::stderr()),
+            use_resolver: true,
+            capabilities,
+            loop_iterations: 0,
+            call_depth: 0,
+            current_line: None,
+            strict_conditions: false,
+            strict_arity: false,
+            strict_globals: false,
+            strict_init: false,
+            boolean_logical: false,
+            optimize: false,
+            breakpoint_hook: None,
+            trace_output: None,
+            random: Random::new(),
+            start: Instant::now(),
+            script_paths: vec![],
+            imported: HashSet::new(),
         };
     }
 
+    // Unresolved variables are looked up dynamically through the environment chain,
+    // so closures see variables declared after them in an enclosing scope
+    pub fn without_resolver(mut self) -> Self {
+        self.use_resolver = false;
+        return self;
+    }
+
+    // Relative to the directory of the file being run, like imports
+    pub fn script_relative_path(&self, path: &str) -> PathBuf {
+        return self
+            .script_paths
+            .last()
+            .and_then(|script| script.parent())
+            .map(|dir| dir.join(path))
+            .unwrap_or_else(|| PathBuf::from(path));
+    }
+
+    pub fn uses_resolver(&self) -> bool {
+        return self.use_resolver;
+    }
+
+    pub fn check_string_length(&self, text: &str) -> RuntimeResult<()> {
+        return check_length(
+            "string",
+            text.chars().count(),
+            self.capabilities.max_string_length,
+        );
+    }
+
+    pub fn check_list_length(&self, length: usize) -> RuntimeResult<()> {
+        return check_length("list", length, self.capabilities.max_list_length);
+    }
+
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        func: impl Fn(&mut Interpreter, Vec<RuntimeValue>) -> RuntimeResult + 'static,
+    ) {
+        let native = NativeFn {
+            name: name.into(),
+            arity,
+            func: Rc::new(func),
+        };
+
+        self.globals.borrow_mut().define(
+            name.into(),
+            RuntimeValue::LoxCallable(LoxCallable::NativeFn(native)),
+        );
+    }
+
+    pub fn environment(&self) -> Rc<RefCell<Environment>> {
+        return Rc::clone(&self.environment);
+    }
+
     pub fn resolve(&mut self, id: ExprId, depth: usize) {
         self.locals.insert(id, depth);
     }
 
+    pub fn capture(&mut self, function: ExprId, name: &LoxStr) {
+        let captures = self.captures.entry(function).or_default();
+
+        if !captures.contains(name) {
+            captures.push(name.clone());
+        }
+    }
+
+    // In the order they're first used. Globals aren't captured, and neither is anything
+    // without the resolver, as it's what works them out
+    pub fn captured_variables(&self, function: &FunctionStmt) -> Vec<LoxStr> {
+        return self.captures.get(&function.id).cloned().unwrap_or_default();
+    }
+
     pub fn interpret(&mut self, statements: Vec<Stmt>) {
-        for mut statement in statements {
-            match self.execute(&mut statement) {
+        if let Err(e) = self.hoist_functions(&statements) {
+            lox::runtime_error(e);
+            return;
+        }
+
+        for statement in statements {
+            match self.execute(&statement) {
+                // Like each loop iteration, so neither a long session nor a long loop
+                // builds up cycles
+                Ok(()) => cycle_collector::collect_if_due(),
                 Err(e) => {
-                    lox::runtime_error(e);
+                    lox::runtime_error(e.into_error().at_line(self.current_line));
                     break;
                 }
-                _ => {}
             }
         }
     }
@@ -57,12 +392,14 @@ impl Interpreter {
         &mut self,
         statements: &Vec<Stmt>,
         environment: Rc<RefCell<Environment>>,
-    ) -> RuntimeResult<()> {
+    ) -> ControlFlowResult {
         let previous = Rc::clone(&self.environment);
 
         self.environment = environment;
 
-        let mut try_execute_block = || -> RuntimeResult<()> {
+        let mut try_execute_block = || -> ControlFlowResult {
+            self.hoist_functions(statements)?;
+
             for statement in statements {
                 self.execute(statement)?;
             }
@@ -78,22 +415,357 @@ impl Interpreter {
     }
 
     fn look_up_variable(&self, name: &Token, expr_id: &ExprId) -> RuntimeResult {
+        let value = if let Some(distance) = self.locals.get(expr_id) {
+            Environment::get_at(Rc::clone(&self.environment), *distance, name)?
+        } else if !self.use_resolver {
+            self.environment.borrow().get(name)?
+        } else {
+            self.globals.borrow().get(name)?
+        };
+
+        if let RuntimeValue::Uninitialized = value {
+            return Err(RuntimeError::UninitializedVariable {
+                name: name.lexeme.clone(),
+                line: name.line,
+            });
+        }
+
+        return Ok(value);
+    }
+
+    fn assign_variable(
+        &mut self,
+        name: &Token,
+        expr_id: &ExprId,
+        value: RuntimeValue,
+    ) -> RuntimeResult<()> {
         if let Some(distance) = self.locals.get(expr_id) {
-            return Environment::get_at(Rc::clone(&self.environment), *distance, name);
+            return Environment::assign_at(
+                Rc::clone(&self.environment),
+                *distance,
+                name.clone(),
+                value,
+            );
+        } else if !self.use_resolver {
+            return self.environment.borrow_mut().assign(name.clone(), value);
         } else {
-            return self.globals.borrow().get(name);
+            return self.globals.borrow_mut().assign(name.clone(), value);
         }
     }
 
-    fn execute(&mut self, stmt: &Stmt) -> RuntimeResult<()> {
+    // Defines a block's functions before running any of it, so they can be called
+    // before their declaration. Each closes over the block's environment as usual.
+    // Only names not yet defined are, and only from their first declaration, so one that
+    // redeclares a function, eg. at the prompt, still only takes over where it's reached.
+    // With `strict_globals` that's an error, so it's reported before anything runs
+    fn hoist_functions(&mut self, statements: &Vec<Stmt>) -> RuntimeResult<()> {
+        let strict = self.strict_globals && Rc::ptr_eq(&self.environment, &self.globals);
+
+        for statement in statements {
+            if let Stmt::Function(stmt) = statement {
+                let defined = self
+                    .environment
+                    .borrow()
+                    .get_own(&stmt.name.lexeme)
+                    .is_some();
+                if defined && !strict {
+                    continue;
+                }
+
+                self.declare_function(stmt)?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn declare_function(&mut self, stmt: &FunctionStmt) -> RuntimeResult<()> {
+        let function = LoxFunction::new(Rc::new(stmt.clone()), Rc::clone(&self.environment), false);
+
+        return self.declare(
+            &stmt.name,
+            RuntimeValue::LoxCallable(LoxCallable::LoxFunction(function)),
+        );
+    }
+
+    // Whether `hoist_functions` already defined `stmt` in the current scope
+    fn is_hoisted(&self, stmt: &FunctionStmt) -> bool {
+        let Some(RuntimeValue::LoxCallable(LoxCallable::LoxFunction(function))) =
+            self.environment.borrow().get_own(&stmt.name.lexeme)
+        else {
+            return false;
+        };
+
+        return function.declaration.id == stmt.id
+            && Rc::ptr_eq(&function.closure, &self.environment);
+    }
+
+    // Defines `name` in the current scope. Redeclaring a global is allowed, as in the book,
+    // unless `strict_globals` is on; the resolver already rejects redeclaring a local
+    fn declare(&mut self, name: &Token, value: RuntimeValue) -> RuntimeResult<()> {
+        if self.strict_globals && Rc::ptr_eq(&self.environment, &self.globals) {
+            return self.environment.borrow_mut().define_new(name, value);
+        }
+
+        self.environment
+            .borrow_mut()
+            .define(name.lexeme.clone(), value);
+
+        return Ok(());
+    }
+
+    // Returns the value the loop was broken out of with, if any
+    fn execute_while(&mut self, stmt: &WhileStmt) -> ControlFlowResult<Option<RuntimeValue>> {
+        while {
+            let condition = self.evaluate(&stmt.condition)?;
+            self.check_condition(&condition, &stmt.keyword)?
+        } {
+            self.count_loop_iteration(&stmt.keyword)?;
+
+            match self.execute(&stmt.body) {
+                Err(ControlFlow::Break(value)) => return Ok(value),
+                Err(ControlFlow::Continue) => {}
+                res => res?,
+            }
+
+            if let Some(increment) = &stmt.increment {
+                self.execute(increment)?;
+            }
+        }
+
+        if let Some(else_branch) = &stmt.else_branch {
+            self.execute(else_branch)?;
+        }
+
+        return Ok(None);
+    }
+
+    // Repeats until a `break`, returning its value if it has one
+    fn execute_loop(&mut self, stmt: &LoopStmt) -> ControlFlowResult<Option<RuntimeValue>> {
+        loop {
+            self.count_loop_iteration(&stmt.keyword)?;
+
+            match self.execute(&stmt.body) {
+                Err(ControlFlow::Break(value)) => return Ok(value),
+                Err(ControlFlow::Continue) => {}
+                res => res?,
+            }
+        }
+    }
+
+    // Conditions must then be booleans, rather than treating everything but `nil` / `false` as true
+    pub fn with_strict_conditions(mut self) -> Self {
+        self.strict_conditions = true;
+        return self;
+    }
+
+    // Imports are then resolved relative to the script rather than the working directory
+    pub fn with_script_path(mut self, path: PathBuf) -> Self {
+        let path = path.canonicalize().unwrap_or(path);
+
+        self.imported.insert(path.clone());
+        self.script_paths.push(path);
+        return self;
+    }
+
+    // Calls must then pass every parameter, even those with defaults
+    pub fn with_strict_arity(mut self) -> Self {
+        self.strict_arity = true;
+        return self;
+    }
+
+    // Redeclaring a global, eg. `var x = 1; var x = 2;`, is then an error
+    pub fn with_strict_globals(mut self) -> Self {
+        self.strict_globals = true;
+        return self;
+    }
+
+    // `and` / `or` then evaluate to `true` / `false` rather than to one of their operands,
+    // eg. `1 and 2` is `true` instead of `2`. They still short-circuit
+    // `var x;` leaves `x` uninitialized rather than nil, so reading it before it's assigned
+    // is an error
+    pub fn with_strict_init(mut self) -> Self {
+        self.strict_init = true;
+        return self;
+    }
+
+    pub fn with_error_output(mut self, output: Box<dyn io::Write>) -> Self {
+        self.err_output = output;
+        return self;
+    }
+
+    pub fn with_boolean_logical(mut self) -> Self {
+        self.boolean_logical = true;
+        return self;
+    }
+
+    pub fn with_optimizer(mut self) -> Self {
+        self.optimize = true;
+        return self;
+    }
+
+    // What to run parsed programs through before they're interpreted, if anything
+    pub fn optimizer(&self) -> Option<Optimizer> {
+        if !self.optimize {
+            return None;
+        }
+
+        return Some(Optimizer::new(self.boolean_logical));
+    }
+
+    // Logs each statement's source line as it runs, and the values of the expressions in it
+    pub fn with_trace(mut self, output: Box<dyn io::Write>) -> Self {
+        self.trace_output = Some(output);
+        return self;
+    }
+
+    pub fn with_breakpoint_hook(mut self, hook: BreakpointHook) -> Self {
+        self.breakpoint_hook = Some(hook);
+        return self;
+    }
+
+    // Fails with how many arguments `function` expects, eg. "1 to 3"
+    pub fn check_argument_count(&self, function: &LoxCallable, argc: usize) -> Result<(), LoxStr> {
+        if self.strict_arity {
+            if argc == function.arity() {
+                return Ok(());
+            }
+
+            return Err(lox_callable::count_arguments(function.arity()));
+        }
+
+        if function.accepts(argc) {
+            return Ok(());
+        }
+
+        return Err(function.expected_arguments());
+    }
+
+    fn count_loop_iteration(&mut self, keyword: &Token) -> RuntimeResult<()> {
+        self.loop_iterations += 1;
+        cycle_collector::collect_if_due();
+
+        if let Some(max) = self.capabilities.max_loop_iterations {
+            if self.loop_iterations > max {
+                return Err(RuntimeError::IterationLimitExceeded {
+                    max,
+                    line: keyword.line,
+                });
+            }
+        }
+
+        return Ok(());
+    }
+
+    pub fn evaluate_in(
+        &mut self,
+        expr: &Expr,
+        environment: Rc<RefCell<Environment>>,
+    ) -> ControlFlowResult<RuntimeValue> {
+        let previous = std::mem::replace(&mut self.environment, environment);
+
+        let res = self.evaluate(expr);
+
+        self.environment = previous;
+
+        return res;
+    }
+
+    fn execute(&mut self, stmt: &Stmt) -> ControlFlowResult {
+        if let Some(line) = stmt.line() {
+            self.current_line = Some(line);
+        }
+
+        if let (Some(output), Some(line)) = (&mut self.trace_output, stmt.line()) {
+            // A function is declared before its block runs, so has nothing to show
+            if !matches!(stmt, Stmt::Function(_)) {
+                let text = lox::source_line(line).unwrap_or_default();
+                let _ = writeln!(output, "[trace] {line} | {}", text.trim());
+            }
+        }
+
         return stmt.accept(self);
     }
 
-    fn evaluate(&mut self, expr: &Expr) -> RuntimeResult {
-        return expr.accept(self);
+    fn evaluate(&mut self, expr: &Expr) -> ControlFlowResult<RuntimeValue> {
+        let value = expr.accept(self)?;
+
+        if self.trace_output.is_some() {
+            self.trace_value(expr, &value);
+        }
+
+        return Ok(value);
+    }
+
+    // Literals and groupings are left out, as their values are already in the source
+    fn trace_value(&mut self, expr: &Expr, value: &RuntimeValue) {
+        let label = match expr {
+            Expr::Literal(_) | Expr::Grouping(_) => return,
+            Expr::Logical(expr) => format!("Logical {}", expr.operator.lexeme),
+            Expr::Unary(expr) => format!("Unary {}", expr.op.1.lexeme),
+            Expr::Binary(expr) => format!("Binary {}", expr.op.1.lexeme),
+            Expr::Call(_) => "Call".to_string(),
+            Expr::Variable(expr) => format!("Variable {}", expr.name.lexeme),
+            Expr::Assignment(expr) => format!("Assign {}", expr.name.lexeme),
+            Expr::Get(expr) => format!("Get {}", expr.name.lexeme),
+            Expr::Set(expr) => format!("Set {}", expr.name.lexeme),
+            Expr::This(_) => "This".to_string(),
+            Expr::Super(expr) => format!("Super {}", expr.method.lexeme),
+            Expr::List(_) => "List".to_string(),
+            Expr::Update(expr) => format!("Update {}", expr.operator.lexeme),
+            Expr::Loop(_) => "Loop".to_string(),
+            Expr::Function(_) => "Function".to_string(),
+        };
+
+        let value = self.stringify(value);
+
+        if let Some(output) = &mut self.trace_output {
+            let _ = writeln!(output, "[trace]   {label} => {value}");
+        }
     }
 
-    fn is_truthy(&self, value: &RuntimeValue) -> bool {
+    // For counts and positions, which must be whole, not negative and fit in a `usize`.
+    // `what` names the value in the error, reported at `token`'s line
+    // `value` is a string or list. The length is checked before building the result, so a
+    // huge count fails without allocating it first
+    fn repeat(&self, value: &RuntimeValue, count: usize) -> RuntimeResult {
+        return match value {
+            RuntimeValue::String(text) => {
+                let length = text.chars().count().saturating_mul(count);
+                check_length("string", length, self.capabilities.max_string_length)?;
+                Ok(RuntimeValue::String(text.repeat(count).into()))
+            }
+            RuntimeValue::List(values) => {
+                let values = values.borrow();
+                self.check_list_length(values.len().saturating_mul(count))?;
+                let repeated = (0..count).flat_map(|_| values.iter().cloned()).collect();
+                Ok(RuntimeValue::list(repeated))
+            }
+            _ => unreachable!(),
+        };
+    }
+
+    pub fn as_index(
+        &self,
+        value: &RuntimeValue,
+        what: &str,
+        token: &Token,
+    ) -> RuntimeResult<usize> {
+        let index = match value {
+            RuntimeValue::Number(number) => {
+                whole_number(*number).and_then(|number| usize::try_from(number).ok())
+            }
+            _ => None,
+        };
+
+        return index.ok_or_else(|| RuntimeError::InvalidIndex {
+            what: what.into(),
+            found: self.stringify(value),
+            line: token.line,
+        });
+    }
+
+    pub fn is_truthy(&self, value: &RuntimeValue) -> bool {
         if let RuntimeValue::Nil = value {
             return false;
         }
@@ -105,24 +777,147 @@ impl Interpreter {
         return true;
     }
 
+    fn check_condition(&self, value: &RuntimeValue, token: &Token) -> RuntimeResult<bool> {
+        if self.strict_conditions && !matches!(value, RuntimeValue::Boolean(_)) {
+            return Err(RuntimeError::NonBooleanCondition {
+                found: self.stringify(value),
+                line: token.line,
+            });
+        }
+
+        return Ok(self.is_truthy(value));
+    }
+
     fn is_equal(&self, left: &RuntimeValue, right: &RuntimeValue) -> bool {
         return left == right;
     }
 
-    fn stringify(&self, value: &RuntimeValue) -> LoxStr {
+    // Binary operators on an instance dispatch to a specially named method
+    // on its class, eg. `a + b` calls `a.__add__(b)`. Only the left operand is checked.
+    // `!=` negates `__eq__`, and both coerce its result to a boolean.
+    fn call_operator_method(
+        &mut self,
+        left: &RuntimeValue,
+        op: &(BinaryExprOp, Token),
+        right: &RuntimeValue,
+    ) -> RuntimeResult<Option<RuntimeValue>> {
+        let RuntimeValue::LoxInstance(instance) = left else {
+            return Ok(None);
+        };
+
+        let name: LoxStr = match op.0 {
+            BinaryExprOp::Plus => "__add__",
+            BinaryExprOp::Minus => "__sub__",
+            BinaryExprOp::Times => "__mul__",
+            BinaryExprOp::Divide => "__div__",
+            BinaryExprOp::Less => "__lt__",
+            BinaryExprOp::LessEqual => "__le__",
+            BinaryExprOp::Greater => "__gt__",
+            BinaryExprOp::GreaterEqual => "__ge__",
+            BinaryExprOp::EqualEqual | BinaryExprOp::NotEqual => "__eq__",
+        }
+        .into();
+
+        let method = instance
+            .class
+            .find_method(&name)
+            .map(|method| method.bind(instance.clone()));
+
+        let Some(mut method) = method else {
+            return Ok(None);
+        };
+
+        if !method.accepts(1) {
+            return Err(RuntimeError::WrongNumberOfArgs {
+                callee: format!("Method '{name}'").into(),
+                expected: LoxCallable::LoxFunction(method).expected_arguments(),
+                found: 1,
+                line: op.1.line,
+            });
+        }
+
+        let result = self.call_counted(op.1.line, |interpreter| {
+            return method.call(interpreter, vec![right.clone()]);
+        })?;
+
+        return Ok(Some(match op.0 {
+            BinaryExprOp::EqualEqual => RuntimeValue::Boolean(self.is_truthy(&result)),
+            BinaryExprOp::NotEqual => RuntimeValue::Boolean(!self.is_truthy(&result)),
+            _ => result,
+        }));
+    }
+
+    // Every call a script makes, including the implicit ones to operator methods and
+    // `close`, goes through here, so it counts towards `max_call_depth`
+    fn call_counted(
+        &mut self,
+        line: usize,
+        call: impl FnOnce(&mut Self) -> RuntimeResult,
+    ) -> RuntimeResult {
+        if let Some(max) = self.capabilities.max_call_depth {
+            if self.call_depth >= max {
+                return Err(RuntimeError::CallDepthExceeded { max, line });
+            }
+        }
+
+        // The callee's statements set the line as they run, so it's put back for the caller's
+        // own errors. Not after an error, which should point at where it happened
+        let caller_line = self.current_line;
+
+        self.call_depth += 1;
+        let res = call(self);
+        self.call_depth -= 1;
+
+        if res.is_ok() {
+            self.current_line = caller_line;
+        }
+
+        return res;
+    }
+
+    // Unlike `print`, doesn't add a newline, so flushes to make sure the text shows up
+    pub fn write(&mut self, text: &str) {
+        write!(self.output, "{text}").expect("Failed to write to output");
+        self.output.flush().expect("Failed to flush output");
+    }
+
+    pub fn write_err(&mut self, text: &str) {
+        write!(self.err_output, "{text}").expect("Failed to write to error output");
+        self.err_output
+            .flush()
+            .expect("Failed to flush error output");
+    }
+
+    // The script's globals as `name = value` lines sorted by name, leaving out the natives,
+    // so the state a program ends in can be compared against a known-good copy
+    pub fn snapshot(&self) -> String {
+        let mut snapshot = String::new();
+
+        for (name, value) in self.globals.borrow().entries() {
+            if is_builtin(&name, &value) {
+                continue;
+            }
+
+            snapshot.push_str(&format!("{name} = {}\n", self.stringify(&value)));
+        }
+
+        return snapshot;
+    }
+
+    pub fn stringify(&self, value: &RuntimeValue) -> LoxStr {
+        return self.stringify_nested(value, &mut vec![]);
+    }
+
+    // `printing` holds the lists and maps this value is inside of. One that holds itself
+    // prints as `[...]` / `{...}` the second time round, rather than recursing forever
+    fn stringify_nested(&self, value: &RuntimeValue, printing: &mut Vec<*const ()>) -> LoxStr {
         match value {
             RuntimeValue::Nil => return "nil".into(),
 
-            RuntimeValue::Number(value) => {
-                let mut text = value.to_string();
-
-                if text.ends_with(".0") {
-                    text.pop(); // 123.0 -> 123.
-                    text.pop(); // 123.  -> 123
-                }
+            // Only for listing variables, eg. with `--snapshot`, as scripts can't read one
+            RuntimeValue::Uninitialized => return "<uninitialized>".into(),
 
-                return text.into();
-            }
+            RuntimeValue::Number(value) => return format_number(*value).into(),
 
             RuntimeValue::String(value) => return value.clone(),
 
@@ -135,36 +930,135 @@ impl Interpreter {
             RuntimeValue::LoxInstance(instance) => {
                 return format!("{} instance", instance.class.name).into()
             }
+
+            RuntimeValue::List(values) => {
+                let pointer = Rc::as_ptr(values) as *const ();
+                if printing.contains(&pointer) {
+                    return "[...]".into();
+                }
+
+                printing.push(pointer);
+                let values = values
+                    .borrow()
+                    .iter()
+                    .map(|value| self.stringify_nested(value, printing))
+                    .collect::<Vec<LoxStr>>();
+                printing.pop();
+
+                return format!("[{}]", values.join(", ")).into();
+            }
+
+            RuntimeValue::Map(map) => {
+                let pointer = Rc::as_ptr(map) as *const ();
+                if printing.contains(&pointer) {
+                    return "{...}".into();
+                }
+
+                printing.push(pointer);
+                let entries = map
+                    .borrow()
+                    .entries()
+                    .iter()
+                    .map(|(key, value)| {
+                        format!(
+                            "{}: {}",
+                            self.stringify_nested(key, printing),
+                            self.stringify_nested(value, printing)
+                        )
+                    })
+                    .collect::<Vec<String>>();
+                printing.pop();
+
+                return format!("{{{}}}", entries.join(", ")).into();
+            }
+        }
+    }
+}
+
+// Numbers print as the shortest text that reads back as the same value, so `0.1 + 0.2`
+// honestly shows `0.30000000000000004`. Whole numbers within i64 range print as plain
+// digits, while anything bigger, or a fraction below 1e-6, uses scientific notation
+// (eg. `1e21`) instead of a screenful of zeros. `-0` keeps its sign.
+fn format_number(value: f64) -> String {
+    if !value.is_finite() {
+        return value.to_string();
+    }
+
+    if value.fract() == 0.0 {
+        if value.abs() < i64::MAX as f64 {
+            if value == 0.0 && value.is_sign_negative() {
+                return "-0".to_string();
+            }
+
+            return (value as i64).to_string();
         }
+
+        return format!("{value:e}");
+    }
+
+    if value.abs() < 1e-6 {
+        return format!("{value:e}");
     }
+
+    return value.to_string();
 }
 
-impl ExprVisitor<RuntimeResult> for Interpreter {
-    fn visit_literal_expr(&mut self, expr: &LiteralExpr) -> RuntimeResult {
+impl ExprVisitor<ControlFlowResult<RuntimeValue>> for Interpreter {
+    fn visit_literal_expr(&mut self, expr: &LiteralExpr) -> ControlFlowResult<RuntimeValue> {
         return Ok(RuntimeValue::from(expr));
     }
 
-    fn visit_logical_expr(&mut self, expr: &LogicalExpr) -> RuntimeResult {
+    fn visit_logical_expr(&mut self, expr: &LogicalExpr) -> ControlFlowResult<RuntimeValue> {
         let left = self.evaluate(&expr.left)?;
 
-        if expr.operator.token_type == TokenType::Or {
-            if self.is_truthy(&left) {
-                return Ok(left);
+        // `??` only checks for `nil`, so it isn't a condition even in strict mode
+        if expr.operator.token_type == TokenType::QuestionQuestion {
+            if let RuntimeValue::Nil = left {
+                return self.evaluate(&expr.right);
             }
+
+            return Ok(left);
+        }
+
+        let left_truthy = self.check_condition(&left, &expr.operator)?;
+
+        // Unlike `and` / `or`, `xor` always needs both sides and yields a boolean
+        if expr.operator.token_type == TokenType::Xor {
+            let right = self.evaluate(&expr.right)?;
+            let right_truthy = self.check_condition(&right, &expr.operator)?;
+
+            return Ok(RuntimeValue::Boolean(left_truthy != right_truthy));
+        }
+
+        let short_circuits = if expr.operator.token_type == TokenType::Or {
+            left_truthy
         } else {
-            if !self.is_truthy(&left) {
-                return Ok(left);
+            !left_truthy
+        };
+
+        if self.boolean_logical {
+            if short_circuits {
+                return Ok(RuntimeValue::Boolean(left_truthy));
             }
+
+            let right = self.evaluate(&expr.right)?;
+            let right_truthy = self.check_condition(&right, &expr.operator)?;
+
+            return Ok(RuntimeValue::Boolean(right_truthy));
+        }
+
+        if short_circuits {
+            return Ok(left);
         }
 
         return self.evaluate(&expr.right);
     }
 
-    fn visit_grouping_expr(&mut self, expr: &GroupingExpr) -> RuntimeResult {
+    fn visit_grouping_expr(&mut self, expr: &GroupingExpr) -> ControlFlowResult<RuntimeValue> {
         return self.evaluate(&expr.expr);
     }
 
-    fn visit_unary_expr(&mut self, expr: &UnaryExpr) -> RuntimeResult {
+    fn visit_unary_expr(&mut self, expr: &UnaryExpr) -> ControlFlowResult<RuntimeValue> {
         let right = self.evaluate(&expr.right)?;
 
         match expr.op.0 {
@@ -174,8 +1068,13 @@ impl ExprVisitor<RuntimeResult> for Interpreter {
                 let RuntimeValue::Number(value) = right else {
                     return Err(RuntimeError::InvalidUnaryExpr {
                         expr: expr.clone(),
-                        details: Some(format!("[{}:{}] Can only apply minus unary operator to numbers.", file!(), line!())),
-                    });
+                        details: Some(format!(
+                            "[{}:{}] Can only apply minus unary operator to numbers.",
+                            file!(),
+                            line!()
+                        )),
+                    }
+                    .into());
                 };
 
                 return Ok(RuntimeValue::Number(-value));
@@ -183,10 +1082,14 @@ impl ExprVisitor<RuntimeResult> for Interpreter {
         }
     }
 
-    fn visit_binary_expr(&mut self, expr: &BinaryExpr) -> RuntimeResult {
+    fn visit_binary_expr(&mut self, expr: &BinaryExpr) -> ControlFlowResult<RuntimeValue> {
         let left = self.evaluate(&expr.left)?;
         let right = self.evaluate(&expr.right)?;
 
+        if let Some(result) = self.call_operator_method(&left, &expr.op, &right)? {
+            return Ok(result);
+        }
+
         match &expr.op.0 {
             BinaryExprOp::Plus => match (left, right) {
                 (RuntimeValue::Number(left), RuntimeValue::Number(right)) => {
@@ -195,17 +1098,38 @@ impl ExprVisitor<RuntimeResult> for Interpreter {
                 (RuntimeValue::String(left), RuntimeValue::String(right)) => {
                     let mut res = left.to_string();
                     res.push_str(&right);
+                    self.check_string_length(&res)?;
                     return Ok(RuntimeValue::String(res.into()));
                 }
-                (_left, _right) => {
-                    return Err(RuntimeError::InvalidBinaryExpr {
-                        expr: expr.clone(),
-                        details: Some(format!(
-                            "[{}:{}] Can only add 2 strings or 2 numbers.",
-                            file!(),
-                            line!()
-                        )),
-                    });
+                (RuntimeValue::List(left), RuntimeValue::List(right)) => {
+                    let mut res = left.borrow().clone();
+                    res.extend(right.borrow().iter().cloned());
+                    self.check_list_length(res.len())?;
+                    return Ok(RuntimeValue::list(res));
+                }
+                (left, right) => {
+                    return Err(invalid_operands(&expr.op, &left, &right).into());
+                }
+            },
+
+            // Repeating a string or list, with the count on either side
+            BinaryExprOp::Times => match (left, right) {
+                (RuntimeValue::Number(left), RuntimeValue::Number(right)) => {
+                    return Ok(RuntimeValue::Number(left * right));
+                }
+                (
+                    value @ (RuntimeValue::String(_) | RuntimeValue::List(_)),
+                    count @ RuntimeValue::Number(_),
+                )
+                | (
+                    count @ RuntimeValue::Number(_),
+                    value @ (RuntimeValue::String(_) | RuntimeValue::List(_)),
+                ) => {
+                    let count = self.as_index(&count, "Repeat count", &expr.op.1)?;
+                    return Ok(self.repeat(&value, count)?);
+                }
+                (left, right) => {
+                    return Err(invalid_operands(&expr.op, &left, &right).into());
                 }
             },
 
@@ -221,7 +1145,8 @@ impl ExprVisitor<RuntimeResult> for Interpreter {
                             file!(),
                             line!()
                         )),
-                    });
+                    }
+                    .into());
                 };
 
                 let RuntimeValue::Number(right) = right else {
@@ -232,13 +1157,15 @@ impl ExprVisitor<RuntimeResult> for Interpreter {
                             file!(),
                             line!()
                         )),
-                    });
+                    }
+                    .into());
                 };
 
                 return Ok(match op {
-                    BinaryExprOp::Plus | BinaryExprOp::EqualEqual | BinaryExprOp::NotEqual => {
-                        unreachable!()
-                    }
+                    BinaryExprOp::Plus
+                    | BinaryExprOp::Times
+                    | BinaryExprOp::EqualEqual
+                    | BinaryExprOp::NotEqual => unreachable!(),
 
                     BinaryExprOp::Greater => RuntimeValue::Boolean(left > right),
                     BinaryExprOp::GreaterEqual => RuntimeValue::Boolean(left >= right),
@@ -247,13 +1174,12 @@ impl ExprVisitor<RuntimeResult> for Interpreter {
 
                     BinaryExprOp::Minus => RuntimeValue::Number(left - right),
                     BinaryExprOp::Divide => RuntimeValue::Number(left / right),
-                    BinaryExprOp::Times => RuntimeValue::Number(left * right),
                 });
             }
         }
     }
 
-    fn visit_call_expr(&mut self, expr: &CallExpr) -> RuntimeResult {
+    fn visit_call_expr(&mut self, expr: &CallExpr) -> ControlFlowResult<RuntimeValue> {
         let callee = self.evaluate(&expr.callee)?;
 
         let mut arguments = vec![];
@@ -265,109 +1191,271 @@ impl ExprVisitor<RuntimeResult> for Interpreter {
             return Err(RuntimeError::InvalidCallable {
                 value: callee,
                 details: Some("Can only call functions and classes".to_string()),
-            });
+            }
+            .into());
         };
 
-        if arguments.len() != function.arity() {
+        if let Err(expected) = self.check_argument_count(&function, arguments.len()) {
             return Err(RuntimeError::WrongNumberOfArgs {
-                expected: function.arity(),
+                callee: function.describe(),
+                expected,
                 found: arguments.len(),
-                details: Some(format!("Expr: {expr:?}")),
-            });
+                line: expr.paren.line,
+            }
+            .into());
         }
 
-        return function.call(self, arguments);
+        let res = self.call_counted(expr.paren.line, |interpreter| {
+            return function.call(interpreter, arguments);
+        });
+
+        return Ok(res?);
     }
 
-    fn visit_variable_expr(&mut self, expr: &VariableExpr) -> RuntimeResult {
+    fn visit_variable_expr(&mut self, expr: &VariableExpr) -> ControlFlowResult<RuntimeValue> {
         // return self.environment.borrow().get(&expr.name);
-        return self.look_up_variable(&expr.name, &expr.id);
+        return Ok(self.look_up_variable(&expr.name, &expr.id)?);
     }
 
-    fn visit_assignment_expr(&mut self, expr: &AssignmentExpr) -> RuntimeResult {
+    fn visit_assignment_expr(&mut self, expr: &AssignmentExpr) -> ControlFlowResult<RuntimeValue> {
         let value = self.evaluate(&expr.value)?;
 
-        if let Some(distance) = self.locals.get(&expr.id) {
-            Environment::assign_at(
-                Rc::clone(&self.environment),
-                *distance,
-                expr.name.clone(),
-                value.clone(),
-            )?;
-        } else {
-            self.globals
-                .borrow_mut()
-                .assign(expr.name.clone(), value.clone())?;
-        }
+        self.assign_variable(&expr.name, &expr.id, value.clone())?;
 
         return Ok(value);
     }
 
-    fn visit_get_expr(&mut self, expr: &GetExpr) -> RuntimeResult {
+    fn visit_update_expr(&mut self, expr: &UpdateExpr) -> ControlFlowResult<RuntimeValue> {
+        let step = |old: &RuntimeValue| -> RuntimeResult {
+            let RuntimeValue::Number(old) = old else {
+                return Err(RuntimeError::InvalidUpdateExpr {
+                    operator: expr.operator.lexeme.clone(),
+                    found: old.type_name().into(),
+                    line: expr.operator.line,
+                });
+            };
+
+            if expr.operator.token_type == TokenType::PlusPlus {
+                return Ok(RuntimeValue::Number(old + 1.0));
+            } else {
+                return Ok(RuntimeValue::Number(old - 1.0));
+            }
+        };
+
+        let (old, new) = match expr.target.as_ref() {
+            Expr::Variable(target) => {
+                let old = self.look_up_variable(&target.name, &target.id)?;
+                let new = step(&old)?;
+
+                self.assign_variable(&target.name, &target.id, new.clone())?;
+
+                (old, new)
+            }
+
+            Expr::Get(target) => {
+                let object = self.evaluate(&target.object)?;
+
+                let RuntimeValue::LoxInstance(mut instance) = object else {
+                    return Err(RuntimeError::InvalidSetExpr {
+                        name: target.name.clone(),
+                        details: Some("Only instances have fields".to_string()),
+                    }
+                    .into());
+                };
+
+                let old = instance.get(&target.name)?;
+                let new = step(&old)?;
+
+                instance.set(target.name.clone(), new.clone())?;
+
+                (old, new)
+            }
+
+            _ => unreachable!("Parser only allows variables and properties"),
+        };
+
+        return Ok(if expr.prefix { new } else { old });
+    }
+
+    fn visit_get_expr(&mut self, expr: &GetExpr) -> ControlFlowResult<RuntimeValue> {
         let object = self.evaluate(&expr.object)?;
 
         if let RuntimeValue::LoxInstance(instance) = object {
-            return instance.get(&expr.name);
+            return Ok(instance.get(&expr.name)?);
+        }
+
+        if expr.optional && matches!(object, RuntimeValue::Nil) {
+            return Ok(RuntimeValue::Nil);
+        }
+
+        if let RuntimeValue::LoxCallable(LoxCallable::LoxClass(class)) = &object {
+            let Some(method) = class.find_static_method(&expr.name.lexeme) else {
+                return Err(RuntimeError::UndefinedStaticMethod {
+                    class: class.name.clone(),
+                    name: expr.name.lexeme.clone(),
+                    line: expr.name.line,
+                }
+                .into());
+            };
+
+            return Ok(RuntimeValue::LoxCallable(LoxCallable::LoxFunction(method)));
+        }
+
+        if let Some(method) = PrimitiveMethod::find(&object, &expr.name.lexeme) {
+            return Ok(RuntimeValue::LoxCallable(LoxCallable::PrimitiveMethod(
+                method,
+            )));
+        }
+
+        if matches!(
+            object,
+            RuntimeValue::Nil
+                | RuntimeValue::Boolean(_)
+                | RuntimeValue::Number(_)
+                | RuntimeValue::String(_)
+        ) {
+            return Err(RuntimeError::UndefinedPrimitiveMethod {
+                type_name: object.type_name().into(),
+                name: expr.name.lexeme.clone(),
+                line: expr.name.line,
+            }
+            .into());
         }
 
         return Err(RuntimeError::InvalidGetExpr {
             name: expr.name.clone(),
             details: Some("Only instances have properties".to_string()),
-        });
+        }
+        .into());
     }
 
-    fn visit_set_expr(&mut self, expr: &SetExpr) -> RuntimeResult {
+    fn visit_set_expr(&mut self, expr: &SetExpr) -> ControlFlowResult<RuntimeValue> {
         let object = self.evaluate(&expr.object)?;
 
         let RuntimeValue::LoxInstance(mut instance) = object else {
             return Err(RuntimeError::InvalidSetExpr {
                 name: expr.name.clone(),
                 details: Some("Only instances have fields".to_string()),
-            });
+            }
+            .into());
         };
 
         let value = self.evaluate(&expr.value)?;
 
-        instance.set(expr.name.clone(), value.clone());
+        instance.set(expr.name.clone(), value.clone())?;
 
         return Ok(value);
     }
 
-    fn visit_this_expr(&mut self, expr: &ThisExpr) -> RuntimeResult {
-        return self.look_up_variable(&expr.keyword, &expr.id);
+    fn visit_this_expr(&mut self, expr: &ThisExpr) -> ControlFlowResult<RuntimeValue> {
+        return Ok(self.look_up_variable(&expr.keyword, &expr.id)?);
+    }
+
+    fn visit_list_expr(&mut self, expr: &ListExpr) -> ControlFlowResult<RuntimeValue> {
+        let mut elements = vec![];
+        for element in &expr.elements {
+            elements.push(self.evaluate(element)?);
+        }
+
+        self.check_list_length(elements.len())?;
+
+        return Ok(RuntimeValue::list(elements));
+    }
+
+    fn visit_super_expr(&mut self, expr: &SuperExpr) -> ControlFlowResult<RuntimeValue> {
+        let this = Token {
+            token_type: TokenType::This,
+            lexeme: "this".into(),
+            line: expr.keyword.line,
+            column: 0,
+        };
+
+        // `this` is always bound in the scope just inside the one holding `super`
+        let (superclass, object) = if let Some(distance) = self.locals.get(&expr.id) {
+            (
+                Environment::get_at(Rc::clone(&self.environment), *distance, &expr.keyword)?,
+                Environment::get_at(Rc::clone(&self.environment), distance - 1, &this)?,
+            )
+        } else {
+            let environment = self.environment.borrow();
+            (environment.get(&expr.keyword)?, environment.get(&this)?)
+        };
+
+        let (
+            RuntimeValue::LoxCallable(LoxCallable::LoxClass(superclass)),
+            RuntimeValue::LoxInstance(object),
+        ) = (superclass, object)
+        else {
+            unreachable!("`super` and `this` are bound by the class declaration and method");
+        };
+
+        let Some(method) = superclass.find_method(&expr.method.lexeme) else {
+            return Err(RuntimeError::UndefinedProperty {
+                name: expr.method.clone(),
+                details: Some(format!("Undefined property '{}'", expr.method.lexeme)),
+            }
+            .into());
+        };
+
+        return Ok(RuntimeValue::LoxCallable(LoxCallable::LoxFunction(
+            method.bind(object),
+        )));
+    }
+
+    fn visit_loop_expr(&mut self, expr: &LoopExpr) -> ControlFlowResult<RuntimeValue> {
+        let value = match expr.loop_stmt.as_ref() {
+            Stmt::While(stmt) => self.execute_while(stmt)?,
+            Stmt::Loop(stmt) => self.execute_loop(stmt)?,
+            stmt => unreachable!("Parser only builds loop expressions from loops: {stmt:?}"),
+        };
+
+        return Ok(value.unwrap_or(RuntimeValue::Nil));
+    }
+
+    fn visit_function_expr(&mut self, expr: &FunctionExpr) -> ControlFlowResult<RuntimeValue> {
+        let function = LoxFunction::new(
+            Rc::clone(&expr.declaration),
+            Rc::clone(&self.environment),
+            false,
+        );
+
+        return Ok(RuntimeValue::LoxCallable(LoxCallable::LoxFunction(
+            function,
+        )));
     }
 }
 
-impl StmtVisitor<RuntimeResult<()>> for Interpreter {
-    fn visit_expression_stmt(&mut self, stmt: &ExpressionStmt) -> RuntimeResult<()> {
+impl StmtVisitor<ControlFlowResult> for Interpreter {
+    fn visit_expression_stmt(&mut self, stmt: &ExpressionStmt) -> ControlFlowResult {
         self.evaluate(&stmt.expr)?;
 
         return Ok(());
     }
 
-    fn visit_print_stmt(&mut self, stmt: &PrintStmt) -> RuntimeResult<()> {
+    fn visit_print_stmt(&mut self, stmt: &PrintStmt) -> ControlFlowResult {
         let value = self.evaluate(&stmt.expr)?;
 
-        println!("{}", self.stringify(&value));
+        let text = self.stringify(&value);
+        writeln!(self.output, "{text}").expect("Failed to write to output");
 
         return Ok(());
     }
 
-    fn visit_variable_stmt(&mut self, stmt: &VariableStmt) -> RuntimeResult<()> {
+    fn visit_variable_stmt(&mut self, stmt: &VariableStmt) -> ControlFlowResult {
         let value = if let Some(initializer) = &stmt.initializer {
             self.evaluate(initializer)?
+        } else if self.strict_init {
+            RuntimeValue::Uninitialized
         } else {
             RuntimeValue::Nil
         };
 
-        self.environment
-            .borrow_mut()
-            .define(stmt.name.lexeme.clone(), value);
+        self.declare(&stmt.name, value)?;
 
         return Ok(());
     }
 
-    fn visit_block_stmt(&mut self, stmt: &BlockStmt) -> RuntimeResult<()> {
+    fn visit_block_stmt(&mut self, stmt: &BlockStmt) -> ControlFlowResult {
         self.execute_block(
             &stmt.stmts,
             Rc::new(RefCell::new(Environment::enclosed(Rc::clone(
@@ -378,10 +1466,10 @@ impl StmtVisitor<RuntimeResult<()>> for Interpreter {
         return Ok(());
     }
 
-    fn visit_if_stmt(&mut self, stmt: &IfStmt) -> RuntimeResult<()> {
-        let mut condition = self.evaluate(&stmt.condition)?;
+    fn visit_if_stmt(&mut self, stmt: &IfStmt) -> ControlFlowResult {
+        let condition = self.evaluate(&stmt.condition)?;
 
-        if self.is_truthy(&mut condition) {
+        if self.check_condition(&condition, &stmt.keyword)? {
             self.execute(&stmt.then_branch)?;
         } else if let Some(else_branch) = &stmt.else_branch {
             self.execute(else_branch)?;
@@ -390,57 +1478,313 @@ impl StmtVisitor<RuntimeResult<()>> for Interpreter {
         return Ok(());
     }
 
-    fn visit_while_stmt(&mut self, stmt: &WhileStmt) -> RuntimeResult<()> {
-        while {
-            let condition = self.evaluate(&stmt.condition)?;
-            self.is_truthy(&condition)
-        } {
-            self.execute(&stmt.body)?;
+    fn visit_while_stmt(&mut self, stmt: &WhileStmt) -> ControlFlowResult {
+        self.execute_while(stmt)?;
+
+        return Ok(());
+    }
+
+    fn visit_loop_stmt(&mut self, stmt: &LoopStmt) -> ControlFlowResult {
+        self.execute_loop(stmt)?;
+
+        return Ok(());
+    }
+
+    fn visit_repeat_stmt(&mut self, stmt: &RepeatStmt) -> ControlFlowResult {
+        // The count is evaluated once, up front
+        let count = self.evaluate(&stmt.count)?;
+        let count = self.as_index(&count, "Repeat count", &stmt.keyword)?;
+
+        for _ in 0..count {
+            self.count_loop_iteration(&stmt.keyword)?;
+
+            match self.execute(&stmt.body) {
+                Err(ControlFlow::Break(_)) => break,
+                Err(ControlFlow::Continue) => {}
+                res => res?,
+            }
         }
 
         return Ok(());
     }
 
-    fn visit_function_stmt(&mut self, stmt: &FunctionStmt) -> RuntimeResult<()> {
-        let name = stmt.name.lexeme.clone();
+    fn visit_for_in_stmt(&mut self, stmt: &ForInStmt) -> ControlFlowResult {
+        // Taken up front, so the body can change the list / map without upsetting the loop
+        let items = match self.evaluate(&stmt.iterable)? {
+            RuntimeValue::List(values) => values.borrow().clone(),
+            RuntimeValue::Map(map) => map.borrow().keys(),
+            iterable => {
+                return Err(RuntimeError::NotIterable {
+                    found: iterable.type_name().into(),
+                    line: stmt.keyword.line,
+                }
+                .into());
+            }
+        };
+
+        for item in items {
+            self.count_loop_iteration(&stmt.keyword)?;
 
-        let function = LoxFunction::new(stmt.clone(), Rc::clone(&self.environment), false);
+            // Each iteration gets its own binding, so closures capture that iteration's item
+            let mut environment = Environment::enclosed(Rc::clone(&self.environment));
+            environment.define(stmt.name.lexeme.clone(), item);
 
-        self.environment.borrow_mut().define(
-            name,
-            RuntimeValue::LoxCallable(LoxCallable::LoxFunction(function)),
-        );
+            let previous =
+                std::mem::replace(&mut self.environment, Rc::new(RefCell::new(environment)));
+            let res = self.execute(&stmt.body);
+            self.environment = previous;
+
+            match res {
+                Err(ControlFlow::Break(_)) => return Ok(()),
+                Err(ControlFlow::Continue) => {}
+                res => res?,
+            }
+        }
+
+        if let Some(else_branch) = &stmt.else_branch {
+            self.execute(else_branch)?;
+        }
+
+        return Ok(());
+    }
+
+    fn visit_debugger_stmt(&mut self, _: &DebuggerStmt) -> ControlFlowResult {
+        // Taken out while it runs, so the hook can be given the interpreter too
+        if let Some(mut hook) = self.breakpoint_hook.take() {
+            let environment = Rc::clone(&self.environment);
+            hook(self, &environment.borrow());
+
+            self.breakpoint_hook = Some(hook);
+        }
 
         return Ok(());
     }
 
-    fn visit_return_stmt(&mut self, stmt: &ReturnStmt) -> RuntimeResult<()> {
+    fn visit_break_stmt(&mut self, stmt: &BreakStmt) -> ControlFlowResult {
         let value = if let Some(value) = &stmt.value {
             Some(self.evaluate(value)?)
         } else {
             None
         };
 
-        return Err(RuntimeError::NonErrorReturnShortCircuit { value });
+        return Err(ControlFlow::Break(value));
     }
 
-    fn visit_class_stmt(&mut self, stmt: &ClassStmt) -> RuntimeResult<()> {
-        self.environment
-            .borrow_mut()
-            .define(stmt.name.lexeme.clone(), RuntimeValue::Nil);
+    fn visit_continue_stmt(&mut self, _: &ContinueStmt) -> ControlFlowResult {
+        return Err(ControlFlow::Continue);
+    }
+
+    fn visit_function_stmt(&mut self, stmt: &FunctionStmt) -> ControlFlowResult {
+        if !self.is_hoisted(stmt) {
+            self.declare_function(stmt)?;
+        }
+
+        return Ok(());
+    }
+
+    fn visit_try_stmt(&mut self, stmt: &TryStmt) -> ControlFlowResult {
+        let res = self.execute_block(
+            &stmt.body,
+            Rc::new(RefCell::new(Environment::enclosed(Rc::clone(
+                &self.environment,
+            )))),
+        );
+
+        // Only errors are caught, a `return` / `break` carries on out. So does hitting
+        // a limit, or a script could catch it and keep going
+        let error = match res {
+            Err(ControlFlow::Error(
+                RuntimeError::IterationLimitExceeded { .. }
+                | RuntimeError::CallDepthExceeded { .. }
+                | RuntimeError::LengthLimitExceeded { .. },
+            )) => return res,
+            Err(ControlFlow::Error(error)) => error,
+            res => return res,
+        };
+
+        let value = match error {
+            RuntimeError::Thrown { value, .. } => value,
+            error => RuntimeValue::String(error.to_string().into()),
+        };
+
+        let mut environment = Environment::enclosed(Rc::clone(&self.environment));
+        environment.define(stmt.catch_name.lexeme.clone(), value);
+
+        return self.execute_block(&stmt.catch_body, Rc::new(RefCell::new(environment)));
+    }
+
+    fn visit_throw_stmt(&mut self, stmt: &ThrowStmt) -> ControlFlowResult {
+        let value = self.evaluate(&stmt.value)?;
+
+        return Err(RuntimeError::Thrown {
+            message: self.stringify(&value),
+            value,
+            line: stmt.keyword.line,
+        }
+        .into());
+    }
 
-        let mut methods = HashMap::new();
+    fn visit_with_stmt(&mut self, stmt: &WithStmt) -> ControlFlowResult {
+        let value = self.evaluate(&stmt.value)?;
+
+        let mut environment = Environment::enclosed(Rc::clone(&self.environment));
+        environment.define(stmt.name.lexeme.clone(), value.clone());
+
+        let res = self.execute_block(&stmt.body, Rc::new(RefCell::new(environment)));
+
+        // `close` runs however the body finishes, even on an error or `return`
+        if let RuntimeValue::LoxInstance(instance) = value {
+            let close = instance
+                .class
+                .find_method(&"close".into())
+                .map(|close| close.bind(instance.clone()));
+
+            if let Some(mut close) = close {
+                let closed = if close.accepts(0) {
+                    self.call_counted(stmt.keyword.line, |interpreter| {
+                        return close.call(interpreter, vec![]);
+                    })
+                } else {
+                    let close = LoxCallable::LoxFunction(close);
+
+                    Err(RuntimeError::WrongNumberOfArgs {
+                        callee: close.describe(),
+                        expected: close.expected_arguments(),
+                        found: 0,
+                        line: stmt.keyword.line,
+                    })
+                };
+
+                // An error from the body is the more useful one to report, but a `return` /
+                // `break` out of it doesn't hide one from `close`
+                if !matches!(res, Err(ControlFlow::Error(_))) {
+                    closed?;
+                }
+            }
+        }
+
+        return res;
+    }
+
+    fn visit_import_stmt(&mut self, stmt: &ImportStmt) -> ControlFlowResult {
+        let import_failed = |details: String| RuntimeError::ImportFailed {
+            path: stmt.path.clone(),
+            details,
+            line: stmt.keyword.line,
+        };
+
+        // Not even to check the path exists, so nothing is found out about the filesystem
+        if !self.capabilities.filesystem {
+            return Err(import_failed("the filesystem is turned off".to_string()).into());
+        }
+
+        let path = self
+            .script_relative_path(&stmt.path)
+            .canonicalize()
+            .map_err(|e| import_failed(lox_callable::io_error_details(&e)))?;
+
+        if self.script_paths.contains(&path) {
+            return Err(RuntimeError::CircularImport {
+                path: stmt.path.clone(),
+                line: stmt.keyword.line,
+            }
+            .into());
+        }
+
+        // Each file only runs once, however many times it's imported
+        if !self.imported.insert(path.clone()) {
+            return Ok(());
+        }
+
+        let source = fs::read_to_string(&path)
+            .map_err(|e| import_failed(lox_callable::io_error_details(&e)))?;
+
+        let importer_source = lox::current_source();
+
+        let Some(statements) = lox::parse_and_resolve(self, &stmt.path, source) else {
+            lox::restore_source(importer_source);
+            return Err(import_failed("it has errors".to_string()).into());
+        };
+
+        self.script_paths.push(path);
+        let res = self.execute_block(&statements, Rc::clone(&self.globals));
+        self.script_paths.pop();
+
+        lox::restore_source(importer_source);
+
+        return res.map_err(|e| e.into_error().into());
+    }
+
+    fn visit_return_stmt(&mut self, stmt: &ReturnStmt) -> ControlFlowResult {
+        let value = if let Some(value) = &stmt.value {
+            Some(self.evaluate(value)?)
+        } else {
+            None
+        };
+
+        return Err(ControlFlow::Return(value));
+    }
+
+    fn visit_class_stmt(&mut self, stmt: &ClassStmt) -> ControlFlowResult {
+        let superclass = if let Some(superclass) = &stmt.superclass {
+            let value = self.visit_variable_expr(superclass)?;
+
+            let RuntimeValue::LoxCallable(LoxCallable::LoxClass(superclass)) = value else {
+                return Err(RuntimeError::InvalidSuperclass {
+                    name: superclass.name.lexeme.clone(),
+                    line: superclass.name.line,
+                }
+                .into());
+            };
+
+            Some(Rc::new(superclass))
+        } else {
+            None
+        };
+
+        self.declare(&stmt.name, RuntimeValue::Nil)?;
+
+        // Methods close over an extra scope holding `super`, matching the resolver
+        let closure = if let Some(superclass) = &superclass {
+            let mut environment = Environment::enclosed(Rc::clone(&self.environment));
+            environment.define(
+                "super".into(),
+                RuntimeValue::LoxCallable(LoxCallable::LoxClass(superclass.as_ref().clone())),
+            );
+
+            Rc::new(RefCell::new(environment))
+        } else {
+            Rc::clone(&self.environment)
+        };
+
+        let mut methods = OrderedMap::new();
         for method in &stmt.methods {
             let function = LoxFunction::new(
-                method.clone(),
-                Rc::clone(&self.environment),
-                method.name.lexeme == "init",
+                Rc::new(method.clone()),
+                Rc::clone(&closure),
+                &*method.name.lexeme == "init",
             );
 
             methods.insert(method.name.lexeme.clone(), function);
         }
 
-        let class = LoxClass::new(stmt.name.lexeme.clone(), Rc::new(RefCell::new(methods)));
+        // Without `this` or `super`, static methods close over the class's own scope
+        let mut static_methods = OrderedMap::new();
+        for method in &stmt.static_methods {
+            let function =
+                LoxFunction::new(Rc::new(method.clone()), Rc::clone(&self.environment), false);
+
+            static_methods.insert(method.name.lexeme.clone(), function);
+        }
+
+        let class = LoxClass::new(
+            stmt.name.lexeme.clone(),
+            superclass,
+            Rc::new(RefCell::new(methods)),
+            Rc::new(static_methods),
+            Rc::new(stmt.fields.clone()),
+            closure,
+        );
         self.environment.borrow_mut().assign(
             stmt.name.clone(),
             RuntimeValue::LoxCallable(LoxCallable::LoxClass(class)),
@@ -449,3 +1793,45 @@ impl StmtVisitor<RuntimeResult<()>> for Interpreter {
         return Ok(());
     }
 }
+
+fn check_length(type_name: &str, length: usize, max: Option<usize>) -> RuntimeResult<()> {
+    if let Some(max) = max {
+        if length > max {
+            return Err(RuntimeError::LengthLimitExceeded {
+                type_name: type_name.into(),
+                max,
+            });
+        }
+    }
+
+    return Ok(());
+}
+
+fn invalid_operands(
+    op: &(BinaryExprOp, Token),
+    left: &RuntimeValue,
+    right: &RuntimeValue,
+) -> RuntimeError {
+    return RuntimeError::InvalidOperands {
+        operator: op.1.lexeme.clone(),
+        left: left.described_type().into(),
+        right: right.described_type().into(),
+        line: op.1.line,
+    };
+}
+
+// Defined in every interpreter alongside the natives
+const CONSTANTS: [(&str, f64); 2] = [("nan", f64::NAN), ("infinity", f64::INFINITY)];
+
+// Whether a global is one every interpreter starts with, and so is left out when listing
+// a script's own. A constant counts until it's reassigned
+pub fn is_builtin(name: &str, value: &RuntimeValue) -> bool {
+    return match value {
+        RuntimeValue::LoxCallable(callable) => callable.is_native(),
+        RuntimeValue::Number(number) => CONSTANTS.iter().any(|(constant, builtin)| {
+            return *constant == name
+                && (number == builtin || (number.is_nan() && builtin.is_nan()));
+        }),
+        _ => false,
+    };
+}
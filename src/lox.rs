@@ -1,26 +1,295 @@
 use crate::{
-    interpreter::Interpreter, parser::Parser, resolver::Resolver, runtime_value::RuntimeError,
-    scanner::Scanner, token::Token, token_type::TokenType,
+    ast::{json::JsonPrinter, stmt::Stmt},
+    capabilities::Capabilities,
+    cycle_collector,
+    environment::Environment,
+    interpreter::{self, BreakpointHook, Interpreter},
+    lox_callable,
+    parser::Parser,
+    resolver::Resolver,
+    runtime_value::{RuntimeError, RuntimeValue},
+    scanner::Scanner,
+    string::LoxStr,
+    token::Token,
+    token_type::TokenType,
 };
 
-use std::{fs, io, path, process};
+use std::{
+    cell::{Cell, RefCell},
+    env, fs, io, path, process,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+pub fn run_lox(args: Vec<String>) -> io::Result<()> {
+    let mut options = Options::default();
+    let mut test_dir = None;
+    let mut bench_dir = None;
+    let mut eval = None;
+    let mut scripts = vec![];
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--test" => test_dir = Some(args.next().unwrap_or_else(|| usage())),
+            "--bench" => bench_dir = Some(args.next().unwrap_or_else(|| usage())),
+            "-e" | "--eval" => eval = Some(args.next().unwrap_or_else(|| usage())),
+            "--version" => {
+                println!("jlox {}", env!("CARGO_PKG_VERSION"));
+                return Ok(());
+            }
+            flag if flag.starts_with("--") => {
+                if !options.parse_flag(flag, &mut args) {
+                    usage();
+                }
+            }
+            _ => scripts.push(arg),
+        }
+    }
+
+    if let Some(test_dir) = test_dir {
+        if !scripts.is_empty() {
+            usage();
+        }
+
+        run_tests(test_dir, &options)?;
+    } else if let Some(bench_dir) = bench_dir {
+        if !scripts.is_empty() {
+            usage();
+        }
 
-pub fn run_lox(mut args: Vec<String>) -> io::Result<()> {
-    if args.len() > 1 {
-        println!("Usage: jlox [script]");
-        process::exit(64);
-    } else if args.len() == 1 {
-        run_file(args.remove(0))?;
+        run_benchmarks(bench_dir, &options)?;
+    } else if let Some(source) = eval {
+        if !scripts.is_empty() {
+            usage();
+        }
+
+        run_eval(source, &options);
+    } else if scripts.len() > 1 {
+        usage();
+    } else if let Some(script) = scripts.pop() {
+        run_file(script, &options)?;
     } else {
-        run_prompt()?;
+        run_prompt(&options)?;
     }
 
     Ok(())
 }
 
+fn usage() -> ! {
+    println!("Usage: jlox [options] [script]");
+    println!("       jlox [options] --test <dir>");
+    println!("       jlox [options] --bench <dir>");
+    println!("       jlox [options] --eval <code>");
+    println!("       jlox --version");
+    println!();
+    println!("Options:");
+    println!("  --no-resolve                 Skip the resolver, looking variables up dynamically");
+    println!(
+        "  --sandbox                    Turn off the filesystem and clock, and limit the rest"
+    );
+    println!("  --no-filesystem              Turn off readFile() and writeFile()");
+    println!("  --max-loop-iterations <n>    Stop with a runtime error after n loop iterations");
+    println!("  --max-call-depth <n>         Stop with a runtime error if calls nest deeper");
+    println!("  --max-string-length <n>      Stop with a runtime error if a string gets longer");
+    println!("  --max-list-length <n>        Stop with a runtime error if a list gets longer");
+    println!("  --strict-conditions          Require conditions to be booleans");
+    println!("  --strict-arity               Require every argument, even those with defaults");
+    println!("  --strict-globals             Reject redeclaring a global variable");
+    println!("  --strict-init                Reject reading a variable before it's assigned");
+    println!("  --boolean-logical            Make 'and' / 'or' evaluate to booleans");
+    println!("  --optimize                   Fold constant expressions, eg. 2 + 3, before running");
+    println!("  --debugger                   List the variables in scope at each 'debugger;'");
+    println!("  --trace                      Log each statement and value to stderr as it runs");
+    println!("  --snapshot                   List the globals once the program has finished");
+    println!(
+        "  --ast-json                   Print the parsed program as JSON instead of running it"
+    );
+    println!("  --ast-diff                   Like --ast-json, with one field per line for diffing");
+    println!("  --check                      Report syntax and resolution errors without running");
+    println!(
+        "  --tokens                     Print the scanned tokens instead of running the program"
+    );
+    println!("  --reprint                    Print the program rebuilt from its tokens, and check");
+    println!("                               that it scans back to the same tokens");
+    println!("  --repl                       Run the script a line at a time, like the prompt");
+    println!("  --history <file>             Append each line entered at the prompt to file");
+    println!("  --optional-semicolons        Let a line break end a statement instead of a ';'");
+    println!("  --keep-comments              Scan comments as tokens, eg. for --tokens");
+    process::exit(64);
+}
+
+#[derive(Default, Clone)]
+struct Options {
+    // Debugging aid: closures then see variables declared after them,
+    // so comparing output with and without it highlights resolver bugs
+    no_resolve: bool,
+    sandbox: bool,
+    no_filesystem: bool,
+    max_loop_iterations: Option<usize>,
+    max_call_depth: Option<usize>,
+    max_string_length: Option<usize>,
+    max_list_length: Option<usize>,
+    strict_conditions: bool,
+    strict_arity: bool,
+    strict_globals: bool,
+    strict_init: bool,
+    boolean_logical: bool,
+    optimize: bool,
+    debugger: bool,
+    trace: bool,
+    snapshot: bool,
+    ast_json: bool,
+    ast_diff: bool,
+    check: bool,
+    repl: bool,
+    history: Option<String>,
+    tokens: bool,
+    reprint: bool,
+    keep_comments: bool,
+    optional_semicolons: bool,
+}
+
+impl Options {
+    // Returns false for an unknown flag or a missing / malformed value
+    fn parse_flag(&mut self, flag: &str, args: &mut impl Iterator<Item = String>) -> bool {
+        match flag {
+            "--no-resolve" => self.no_resolve = true,
+            "--sandbox" => self.sandbox = true,
+            "--no-filesystem" => self.no_filesystem = true,
+            "--strict-conditions" => self.strict_conditions = true,
+            "--strict-arity" => self.strict_arity = true,
+            "--strict-globals" => self.strict_globals = true,
+            "--strict-init" => self.strict_init = true,
+            "--boolean-logical" => self.boolean_logical = true,
+            "--optimize" => self.optimize = true,
+            "--debugger" => self.debugger = true,
+            "--trace" => self.trace = true,
+            "--snapshot" => self.snapshot = true,
+            "--ast-json" => self.ast_json = true,
+            "--ast-diff" => self.ast_diff = true,
+            "--check" => self.check = true,
+            "--repl" => self.repl = true,
+            "--history" => match args.next() {
+                Some(path) => self.history = Some(path),
+                None => return false,
+            },
+            "--tokens" => self.tokens = true,
+            "--reprint" => self.reprint = true,
+            "--keep-comments" => self.keep_comments = true,
+            "--optional-semicolons" => self.optional_semicolons = true,
+            "--max-loop-iterations" => match args.next().and_then(|max| max.parse().ok()) {
+                Some(max) => self.max_loop_iterations = Some(max),
+                None => return false,
+            },
+            "--max-call-depth" => match args.next().and_then(|max| max.parse().ok()) {
+                Some(max) => self.max_call_depth = Some(max),
+                None => return false,
+            },
+            "--max-string-length" => match args.next().and_then(|max| max.parse().ok()) {
+                Some(max) => self.max_string_length = Some(max),
+                None => return false,
+            },
+            "--max-list-length" => match args.next().and_then(|max| max.parse().ok()) {
+                Some(max) => self.max_list_length = Some(max),
+                None => return false,
+            },
+            _ => return false,
+        }
+
+        return true;
+    }
+
+    // Limits given explicitly override the sandbox's
+    fn capabilities(&self) -> Capabilities {
+        let mut capabilities = if self.sandbox {
+            Capabilities::sandboxed()
+        } else {
+            Capabilities::all()
+        };
+
+        if self.no_filesystem {
+            capabilities.filesystem = false;
+        }
+
+        let limits = [
+            (
+                &mut capabilities.max_loop_iterations,
+                self.max_loop_iterations,
+            ),
+            (&mut capabilities.max_call_depth, self.max_call_depth),
+            (&mut capabilities.max_string_length, self.max_string_length),
+            (&mut capabilities.max_list_length, self.max_list_length),
+        ];
+
+        for (limit, max) in limits {
+            if max.is_some() {
+                *limit = max;
+            }
+        }
+
+        return capabilities;
+    }
+
+    fn interpreter(&self, output: Box<dyn io::Write>) -> Interpreter {
+        return self.configure(Interpreter::with_capabilities(output, self.capabilities()));
+    }
+
+    fn configure(&self, mut interpreter: Interpreter) -> Interpreter {
+        if self.no_resolve {
+            interpreter = interpreter.without_resolver();
+        }
+
+        if self.strict_conditions {
+            interpreter = interpreter.with_strict_conditions();
+        }
+
+        if self.strict_arity {
+            interpreter = interpreter.with_strict_arity();
+        }
+
+        if self.strict_globals {
+            interpreter = interpreter.with_strict_globals();
+        }
+
+        if self.strict_init {
+            interpreter = interpreter.with_strict_init();
+        }
+
+        if self.boolean_logical {
+            interpreter = interpreter.with_boolean_logical();
+        }
+
+        if self.optimize {
+            interpreter = interpreter.with_optimizer();
+        }
+
+        if self.debugger {
+            interpreter = interpreter.with_breakpoint_hook(debugger_hook(io::stdout()));
+        }
+
+        if self.trace {
+            interpreter = interpreter.with_trace(Box::new(io::stderr()));
+        }
+
+        return interpreter;
+    }
+}
+
 static mut HAD_ERROR: bool = false;
 static mut HAD_RUNTIME_ERROR: bool = false;
 
+thread_local! {
+    // Lets the test runner check the message a script stopped with
+    static LAST_RUNTIME_ERROR: Cell<Option<String>> = const { Cell::new(None) };
+
+    // What's being scanned and parsed, so errors can quote the offending line
+    static SOURCE: RefCell<Option<Source>> = const { RefCell::new(None) };
+
+    // Set by the test runner to collect the error output, which otherwise only goes to stderr
+    static CAPTURED_ERRORS: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
 pub fn had_error() -> bool {
     return unsafe { HAD_ERROR };
 }
@@ -29,14 +298,51 @@ pub fn had_runtime_error() -> bool {
     return unsafe { HAD_RUNTIME_ERROR };
 }
 
-fn run_file(path: String) -> io::Result<()> {
-    let mut interpreter = Interpreter::new();
+fn run_file(path: String, options: &Options) -> io::Result<()> {
+    let path = path::PathBuf::from(path);
 
-    let content = fs::read_to_string(path::PathBuf::from(path))?;
+    if path.extension().is_none_or(|ext| ext != "lox") {
+        eprintln!(
+            "Warning: '{}' doesn't have a .lox extension",
+            path.display()
+        );
+    }
+
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) => {
+            let details = lox_callable::io_error_details(&e);
+            eprintln!("Could not read '{}': {details}", path.display());
+            process::exit(66);
+        }
+    };
+
+    let mut interpreter = options
+        .interpreter(Box::new(io::stdout()))
+        .with_script_path(path);
+
+    if options.repl {
+        prompt(&mut interpreter, content.as_bytes(), &mut io::sink(), None)?;
+    } else {
+        run(&mut interpreter, content, options);
+    }
+
+    exit_if_failed();
+
+    Ok(())
+}
+
+// Runs a snippet given on the command line, eg. `jlox -e 'print 1 + 1;'`
+fn run_eval(source: String, options: &Options) {
+    let mut interpreter = options.interpreter(Box::new(io::stdout()));
 
-    run(&mut interpreter, content);
+    run(&mut interpreter, source, options);
+
+    exit_if_failed();
+}
 
-    // Indicate an error in the exit code
+// Indicate an error in the exit code
+fn exit_if_failed() {
     if had_error() {
         process::exit(65);
     }
@@ -44,27 +350,91 @@ fn run_file(path: String) -> io::Result<()> {
     if had_runtime_error() {
         process::exit(70);
     }
-
-    Ok(())
 }
 
-fn run_prompt() -> io::Result<()> {
-    let mut interpreter = Interpreter::new();
+fn run_prompt(options: &Options) -> io::Result<()> {
+    let mut interpreter = options.interpreter(Box::new(io::stdout()));
 
+    let history = match &options.history {
+        Some(path) => Some(
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?,
+        ),
+        None => None,
+    };
+
+    return prompt(
+        &mut interpreter,
+        io::stdin().lock(),
+        &mut io::stdout(),
+        history,
+    );
+}
+
+// Reads and runs one entry at a time from `input`, an entry being as many lines as it takes
+// to close every bracket, string and block comment. Each complete entry is appended to
+// `history`, which `--repl` can then replay
+fn prompt(
+    interpreter: &mut Interpreter,
+    mut input: impl io::BufRead,
+    prompts: &mut impl io::Write,
+    mut history: Option<fs::File>,
+) -> io::Result<()> {
     loop {
-        // Flushing normally only happens on new-line,
-        // Have to force in order to print on same line as accepting input
-        print!("> ");
-        io::Write::flush(&mut io::stdout())?;
+        let mut entry = String::new();
+
+        loop {
+            // Flushing normally only happens on new-line,
+            // Have to force in order to print on same line as accepting input
+            write!(prompts, "{}", if entry.is_empty() { "> " } else { "... " })?;
+            prompts.flush()?;
 
-        let mut line = String::new();
-        let bytes_read = io::stdin().read_line(&mut line)?;
+            let bytes_read = input.read_line(&mut entry)?;
 
-        if bytes_read == 0 {
+            // Whatever's left at the end of the input still runs, to report what's missing
+            if bytes_read == 0 || !is_incomplete(&entry) {
+                break;
+            }
+        }
+
+        if entry.is_empty() {
             break;
         }
 
-        run(&mut interpreter, line);
+        if entry.trim() == ":vars" {
+            print_vars(interpreter);
+            continue;
+        }
+
+        if let Some(history) = &mut history {
+            io::Write::write_all(history, entry.as_bytes())?;
+        }
+
+        let entry: LoxStr = entry.into();
+        set_source(&entry);
+
+        let mut tokens = Scanner::new(entry).scan_tokens();
+
+        // Be forgiving of a missing `;` at the end of the line, but only that, so other
+        // errors are reported for the tokens as entered
+        if misses_final_semicolon(&tokens) {
+            if let [.., last, _eof] = tokens.as_slice() {
+                let semicolon = Token {
+                    token_type: TokenType::Semicolon,
+                    lexeme: ";".into(),
+                    line: last.line,
+                    column: last.column + last.lexeme.chars().count(),
+                };
+
+                tokens.insert(tokens.len() - 1, semicolon);
+            }
+        }
+
+        if let Some(statements) = parse_and_resolve_tokens(interpreter, tokens) {
+            interpreter.interpret(statements);
+        }
 
         unsafe {
             HAD_ERROR = false;
@@ -74,51 +444,681 @@ fn run_prompt() -> io::Result<()> {
     Ok(())
 }
 
-fn run(interpreter: &mut Interpreter, source: String) {
-    let scanner = Scanner::new(source.into());
+// Whether parsing first fails for want of a `;` at the very end
+fn misses_final_semicolon(tokens: &[Token]) -> bool {
+    let mut parser = Parser::new(tokens.to_vec()).quiet();
+    parser.parse();
+
+    return parser.first_error().is_some_and(|(token, message)| {
+        return token.token_type == TokenType::EOF && message.starts_with("Expect ';'");
+    });
+}
+
+// Whether `source` leaves a bracket, string or block comment open, so the prompt should
+// read another line before running it. Too many closing brackets is left for the parser
+fn is_incomplete(source: &str) -> bool {
+    let mut depth = 0;
+    let mut chars = source.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '(' | '{' | '[' => depth += 1,
+            ')' | '}' | ']' => depth -= 1,
+            // The guard skips to the closing quote when there is one
+            '"' if !chars.any(|c| c == '"') => return true,
+            '/' if chars.peek() == Some(&'/') => {
+                chars.find(|c| *c == '\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+
+                let mut previous = ' ';
+                let closed = chars.any(|c| {
+                    let closes = previous == '*' && c == '/';
+                    previous = c;
+                    return closes;
+                });
+
+                if !closed {
+                    return true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    return depth > 0;
+}
+
+// Lists the variables defined so far, leaving out the built-in natives and constants
+// To the interpreter's output, like what the entries print
+fn print_vars(interpreter: &mut Interpreter) {
+    let environment = interpreter.environment();
+
+    let mut vars = vec![];
+    write_vars(interpreter, &environment.borrow(), &mut vars);
+    interpreter.write(&String::from_utf8_lossy(&vars));
+}
+
+// Every binding visible from `environment`, leaving out the built-in natives and constants
+fn write_vars(interpreter: &Interpreter, environment: &Environment, out: &mut impl io::Write) {
+    for (name, value) in environment.all_entries() {
+        if interpreter::is_builtin(&name, &value) {
+            continue;
+        }
+
+        let _ = writeln!(out, "{name} = {}", interpreter.stringify(&value));
+    }
+}
+
+// For `--debugger`, listing the variables in scope at each `debugger;`
+fn debugger_hook(mut out: impl io::Write + 'static) -> BreakpointHook {
+    return Box::new(move |interpreter, environment| {
+        let _ = writeln!(out, "[debugger]");
+        write_vars(interpreter, environment, &mut out);
+    });
+}
+
+// Runs every .lox file under `dir`, comparing what it prints against the
+// `// expect: ...`, `// expect runtime error: ...` and `// expect error: ...` comments
+// in its source.
+// A file can set extra flags for itself with `// options: ...`
+fn run_tests(dir: String, options: &Options) -> io::Result<()> {
+    let mut paths = vec![];
+    collect_test_paths(path::PathBuf::from(dir), &mut paths)?;
+    paths.sort();
+
+    let mut failed = 0;
+
+    for path in &paths {
+        let content = fs::read_to_string(path)?;
+        let (expected, actual) = run_test_source(path, content, options);
+
+        if actual == expected {
+            println!("PASS {}", path.display());
+        } else {
+            failed += 1;
+
+            println!("FAIL {}", path.display());
+            println!("  expected: {expected:?}");
+            println!("  actual:   {actual:?}");
+        }
+    }
+
+    println!();
+    println!("{} passed, {failed} failed", paths.len() - failed);
+
+    if failed > 0 {
+        process::exit(1);
+    }
+
+    Ok(())
+}
+
+// Runs a test file, returning what it expects and what it actually output
+fn run_test_source(
+    path: &path::Path,
+    content: String,
+    options: &Options,
+) -> (Vec<String>, Vec<String>) {
+    let expected = content
+        .lines()
+        .filter_map(|line| {
+            if let Some((_, code)) = line.split_once("// expect exit code: ") {
+                return Some(format!("exit code {code}"));
+            }
+
+            return line
+                .split_once("// expect: ")
+                .or_else(|| line.split_once("// expect runtime error: "))
+                .or_else(|| line.split_once("// expect error: "))
+                .map(|(_, expected)| expected.to_string());
+        })
+        .collect::<Vec<String>>();
+
+    // One argument per line, so an argument can have spaces in it
+    let args = content
+        .lines()
+        .filter_map(|line| line.split_once("// arg: "))
+        .map(|(_, arg)| arg.to_string())
+        .collect::<Vec<String>>();
+
+    if !args.is_empty() {
+        return (expected, run_command_test(path, args));
+    }
+
+    let mut file_options = options.clone();
+    let file_flags = content
+        .lines()
+        .filter_map(|line| line.split_once("// options: "))
+        .flat_map(|(_, flags)| flags.split_whitespace().map(|flag| flag.to_string()))
+        .collect::<Vec<String>>();
+
+    let mut test_natives = false;
+    let mut file_flags = file_flags.into_iter();
+    while let Some(flag) = file_flags.next() {
+        // Not an option for scripts, so not known to `parse_flag`
+        if flag == "--test-natives" {
+            test_natives = true;
+        } else if !file_options.parse_flag(&flag, &mut file_flags) {
+            eprintln!("Invalid test option '{flag}' in {}", path.display());
+            process::exit(64);
+        }
+    }
+
+    let output = CapturedOutput::default();
+    let err_output = CapturedOutput::default();
+    let mut interpreter = file_options
+        .interpreter(Box::new(output.clone()))
+        .with_error_output(Box::new(err_output.clone()))
+        .with_script_path(path.to_path_buf());
+
+    // Listed alongside what the program prints, so it can be checked too
+    if file_options.debugger {
+        interpreter = interpreter.with_breakpoint_hook(debugger_hook(output.clone()));
+    }
+
+    if file_options.trace {
+        interpreter = interpreter.with_trace(Box::new(output.clone()));
+    }
+
+    if test_natives {
+        define_test_natives(&mut interpreter);
+    }
+
+    CAPTURED_ERRORS.with(|captured| *captured.borrow_mut() = Some(vec![]));
+
+    if file_options.repl {
+        // Reading from a string can't fail
+        let _ = prompt(&mut interpreter, content.as_bytes(), &mut io::sink(), None);
+    } else {
+        run(&mut interpreter, content, &file_options);
+    }
+
+    unsafe {
+        HAD_ERROR = false;
+        HAD_RUNTIME_ERROR = false;
+    }
+
+    let runtime_error = LAST_RUNTIME_ERROR.with(|last| last.take());
+
+    // Errors are reported before anything runs, so come first, then what the script
+    // wrote to the error output
+    let mut actual = CAPTURED_ERRORS
+        .with(|captured| captured.borrow_mut().take())
+        .unwrap_or_default();
+
+    actual.extend(
+        String::from_utf8_lossy(&err_output.0.borrow())
+            .lines()
+            .map(|line| line.to_string()),
+    );
+
+    actual.extend(
+        String::from_utf8_lossy(&output.0.borrow())
+            .lines()
+            .map(|line| line.to_string()),
+    );
+
+    actual.extend(runtime_error);
+
+    return (expected, actual);
+}
+
+// Natives only fixtures can have, with `// options: --test-natives`, to check natives
+// registered by embedders, and that closures in reference cycles are freed
+fn define_test_natives(interpreter: &mut Interpreter) {
+    interpreter.define_native("collectCycles", 0, |_, _| {
+        return Ok(RuntimeValue::Number(cycle_collector::collect() as f64));
+    });
+    interpreter.define_native("double", 1, |_, arguments| match arguments[0] {
+        RuntimeValue::Number(number) => return Ok(RuntimeValue::Number(number * 2.0)),
+        ref value => return Err(lox_callable::invalid_argument("double", "a number", value)),
+    });
+}
+
+// Runs jlox itself with a test file's `// arg: ...` comments, from the file's directory, for
+// what only shows from the outside, eg. the exit code. Lists what it wrote to stderr, then
+// stdout, then the exit code
+fn run_command_test(path: &path::Path, args: Vec<String>) -> Vec<String> {
+    let dir = path.parent().unwrap_or(path::Path::new("."));
+    let output = env::current_exe().and_then(|jlox| {
+        return process::Command::new(jlox)
+            .args(args)
+            .current_dir(dir)
+            .stdin(process::Stdio::null())
+            .output();
+    });
+
+    let output = match output {
+        Ok(output) => output,
+        Err(e) => return vec![format!("Could not run jlox: {e}")],
+    };
+
+    let mut actual = vec![];
+    for stream in [&output.stderr, &output.stdout] {
+        actual.extend(
+            String::from_utf8_lossy(stream)
+                .lines()
+                .map(|line| line.to_string()),
+        );
+    }
+
+    match output.status.code() {
+        Some(code) => actual.push(format!("exit code {code}")),
+        None => actual.push("exit code none".to_string()),
+    }
+
+    return actual;
+}
+
+const BENCH_RUNS: usize = 5;
+
+// Times every .lox file under `dir` through the whole pipeline, from scanning to running.
+// Each run's output is checked against its `// expect: ...` comments, like a test, so a
+// change that speeds things up by getting them wrong doesn't go unnoticed
+fn run_benchmarks(dir: String, options: &Options) -> io::Result<()> {
+    let mut paths = vec![];
+    collect_test_paths(path::PathBuf::from(dir), &mut paths)?;
+    paths.sort();
+
+    let mut failed = 0;
+
+    for path in &paths {
+        let content = fs::read_to_string(path)?;
+        let mut timings = vec![];
+
+        for _ in 0..BENCH_RUNS {
+            let start = Instant::now();
+            let (expected, actual) = run_test_source(path, content.clone(), options);
+            timings.push(start.elapsed());
+
+            if actual != expected {
+                failed += 1;
+
+                println!("FAIL {}", path.display());
+                println!("  expected: {expected:?}");
+                println!("  actual:   {actual:?}");
+                break;
+            }
+        }
+
+        if timings.len() == BENCH_RUNS {
+            let min = timings.iter().min().unwrap();
+            let mean = timings.iter().sum::<Duration>() / BENCH_RUNS as u32;
+
+            println!(
+                "{:<40} min {:>9.3} ms   mean {:>9.3} ms",
+                path.display(),
+                min.as_secs_f64() * 1000.0,
+                mean.as_secs_f64() * 1000.0
+            );
+        }
+    }
+
+    if failed > 0 {
+        println!();
+        println!("{failed} failed");
+        process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn collect_test_paths(dir: path::PathBuf, paths: &mut Vec<path::PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            collect_test_paths(path, paths)?;
+        } else if path.extension().is_some_and(|ext| ext == "lox") {
+            paths.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Default)]
+struct CapturedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl io::Write for CapturedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        return self.0.borrow_mut().write(buf);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return Ok(());
+    }
+}
+
+fn run(interpreter: &mut Interpreter, source: String, options: &Options) {
+    let source: LoxStr = source.into();
+    set_source(&source);
+
+    let mut scanner = Scanner::new(source);
+    if options.keep_comments {
+        scanner = scanner.keeping_comments();
+    }
+
+    if options.optional_semicolons {
+        scanner = scanner.inserting_semicolons();
+    }
+
     let tokens = scanner.scan_tokens();
 
+    if options.tokens {
+        for token in &tokens {
+            interpreter.write(&format!("{}\n", describe_token(token)));
+        }
+
+        return;
+    }
+
+    if options.reprint {
+        let text = reprint(&tokens);
+        interpreter.write(&format!("{text}\n"));
+
+        let mut scanner = Scanner::new(text.into());
+        if options.keep_comments {
+            scanner = scanner.keeping_comments();
+        }
+
+        // Positions aside, as whitespace isn't kept
+        let rescanned = scanner.scan_tokens();
+        let same = |(token, rescanned): &(&Token, &Token)| {
+            return token.token_type == rescanned.token_type
+                && token.to_string() == rescanned.to_string();
+        };
+
+        if let Some((token, _)) = tokens.iter().zip(&rescanned).find(|pair| !same(pair)) {
+            error(
+                token.line,
+                None,
+                "The reprinted program scans back differently",
+            );
+        } else if rescanned.len() != tokens.len() {
+            let line = tokens.last().map(|token| token.line).unwrap_or_default();
+            error(line, None, "The reprinted program scans back differently");
+        }
+
+        return;
+    }
+
+    if options.ast_json || options.ast_diff {
+        let mut statements = Parser::new(tokens).parse();
+
+        // Only the syntax is exported, so there's no need to resolve
+        if !had_error() {
+            // Shows what --optimize folded
+            if let Some(mut optimizer) = interpreter.optimizer() {
+                statements = optimizer.optimize(&statements);
+            }
+
+            let json = if options.ast_diff {
+                JsonPrinter.print_indented(&statements)
+            } else {
+                JsonPrinter.print(&statements)
+            };
+            interpreter.write(&format!("{json}\n"));
+        }
+
+        return;
+    }
+
+    // Every error is still reported, and sets the exit code, but nothing runs. The resolver
+    // goes over whatever parsed, so a syntax error doesn't hide the errors after it, only
+    // those in the statement it's in
+    if options.check {
+        let statements = Parser::new(tokens).parse();
+
+        if interpreter.uses_resolver() {
+            Resolver::new(interpreter).resolve(&statements);
+        }
+
+        return;
+    }
+
+    if let Some(statements) = parse_and_resolve_tokens(interpreter, tokens) {
+        interpreter.interpret(statements);
+
+        if options.snapshot {
+            let snapshot = interpreter.snapshot();
+            interpreter.write(&snapshot);
+        }
+    }
+}
+
+// The tokens' text, each on the line it started on, with a space between those sharing one.
+// Other whitespace isn't kept, so it's the same program rather than the same file
+fn reprint(tokens: &[Token]) -> String {
+    let mut text = String::new();
+    let mut line = None;
+
+    for token in tokens {
+        if token.token_type == TokenType::EOF {
+            break;
+        }
+
+        let token_text = token.to_string();
+
+        // Multi-line tokens are on the line they end on
+        let start = token.line - token_text.matches('\n').count();
+
+        match line {
+            Some(line) if start > line => text.push_str(&"\n".repeat(start - line)),
+            Some(_) => text.push(' '),
+            None => {}
+        }
+
+        text.push_str(&token_text);
+        line = Some(token.line);
+    }
+
+    return text;
+}
+
+// Where a token is, what kind it is, and its text, eg. "3:5 Identifier count".
+// A comment's text is left out since a block comment can span lines
+fn describe_token(token: &Token) -> String {
+    let position = format!("{}:{}", token.line, token.column);
+
+    return match &token.token_type {
+        TokenType::LineComment(_) => format!("{position} LineComment"),
+        TokenType::BlockComment(_) => format!("{position} BlockComment"),
+        TokenType::EOF => format!("{position} EOF"),
+        token_type => {
+            // Just the variant's name, since literals show their value after it
+            let kind = format!("{token_type:?}");
+            let kind = kind.split('(').next().unwrap_or_default();
+
+            format!("{position} {kind} {token}")
+        }
+    };
+}
+
+// Returns `None` once any errors have been reported
+pub fn parse_and_resolve(
+    interpreter: &mut Interpreter,
+    path: &LoxStr,
+    source: String,
+) -> Option<Vec<Stmt>> {
+    let source: LoxStr = source.into();
+    SOURCE.with(|current| {
+        *current.borrow_mut() = Some(Source {
+            text: source.clone(),
+            path: Some(path.clone()),
+        })
+    });
+
+    let scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens();
+
+    return parse_and_resolve_tokens(interpreter, tokens);
+}
+
+fn parse_and_resolve_tokens(
+    interpreter: &mut Interpreter,
+    tokens: Vec<Token>,
+) -> Option<Vec<Stmt>> {
     let mut parser = Parser::new(tokens);
     let statements = parser.parse();
 
     // Stop if there was a syntax error
     if had_error() {
-        return;
+        return None;
     }
 
-    let mut resolver = Resolver::new(interpreter);
-    resolver.resolve(&statements);
+    if interpreter.uses_resolver() {
+        let mut resolver = Resolver::new(interpreter);
+        resolver.resolve(&statements);
 
-    // Stop if there was a resolution error
-    if had_error() {
-        return;
+        // Stop if there was a resolution error
+        if had_error() {
+            return None;
+        }
     }
 
-    interpreter.interpret(statements);
+    if let Some(mut optimizer) = interpreter.optimizer() {
+        return Some(optimizer.optimize(&statements));
+    }
+
+    return Some(statements);
+}
+
+// The text being run, and the path it was imported by, if it was
+#[derive(Clone)]
+pub struct Source {
+    text: LoxStr,
+    path: Option<LoxStr>,
+}
+
+fn set_source(source: &LoxStr) {
+    SOURCE.with(|current| {
+        *current.borrow_mut() = Some(Source {
+            text: source.clone(),
+            path: None,
+        })
+    });
+}
+
+// Scanning an import replaces the source, so the importer takes its own back after
+pub fn current_source() -> Option<Source> {
+    return SOURCE.with(|current| current.borrow().clone());
+}
+
+pub fn restore_source(source: Option<Source>) {
+    SOURCE.with(|current| *current.borrow_mut() = source);
+}
+
+// eg. `line 3`, or `lib/shapes.lox line 3` for a line of an imported file
+fn location(line: usize) -> String {
+    return SOURCE.with(
+        |source| match source.borrow().as_ref().and_then(|s| s.path.as_ref()) {
+            Some(path) => format!("{path} line {line}"),
+            None => format!("line {line}"),
+        },
+    );
 }
 
-pub fn error(line: usize, message: &str) {
-    report(line, "", message);
+pub fn error(line: usize, column: Option<usize>, message: &str) {
+    report(line, column, "", message);
 }
 
 pub fn runtime_error(error: RuntimeError) {
     eprintln!("{}", error);
 
     unsafe { HAD_RUNTIME_ERROR = true };
+
+    LAST_RUNTIME_ERROR.with(|last| last.set(Some(error.to_string())));
 }
 
 pub fn token_error(token: Token, message: &str) {
+    // Made-up tokens have no column to point at
+    let column = Some(token.column).filter(|column| *column > 0);
+
     if token.token_type == TokenType::EOF {
-        report(token.line, " at end", message);
+        report(token.line, column, " at end", message);
     } else {
-        report(token.line, &format!(" at '{}'", token.lexeme), message);
+        report(
+            token.line,
+            column,
+            &format!(" at '{}'", token.lexeme),
+            message,
+        );
     }
 }
 
-fn report(line: usize, where_: &str, message: &str) {
-    eprintln!("[line {line}] Error{where_}: {message}");
+// Reports a diagnostic without failing the run
+pub fn token_warning(token: Token, message: &str) {
+    write_diagnostic(vec![format!(
+        "[{}] Warning at '{}': {message}",
+        location(token.line),
+        token.lexeme
+    )]);
+}
+
+fn report(line: usize, column: Option<usize>, where_: &str, message: &str) {
+    let mut output = vec![format!("[{}] Error{where_}: {message}", location(line))];
+
+    if let Some(column) = column {
+        output.extend(snippet(line, column));
+    }
+
+    write_diagnostic(output);
 
     unsafe {
         HAD_ERROR = true;
     }
 }
+
+// To stderr, and to what the test runner collects
+fn write_diagnostic(output: Vec<String>) {
+    for output in &output {
+        eprintln!("{output}");
+    }
+
+    CAPTURED_ERRORS.with(|captured| {
+        if let Some(captured) = captured.borrow_mut().as_mut() {
+            captured.extend(output);
+        }
+    });
+}
+
+// Line `line` of what was last scanned, counting from 1
+pub fn source_line(line: usize) -> Option<String> {
+    return SOURCE.with(|source| {
+        return source.borrow().as_ref().and_then(|source| {
+            source
+                .text
+                .lines()
+                .nth(line.checked_sub(1)?)
+                .map(|text| text.to_string())
+        });
+    });
+}
+
+// Quotes the source line with a caret under `column`, eg.
+//   3 | print (1 + ;
+//     |            ^
+// Nothing for a blank line, like the end of a file that ends in a newline
+fn snippet(line: usize, column: usize) -> Vec<String> {
+    let Some(text) = source_line(line) else {
+        return vec![];
+    };
+
+    if text.trim().is_empty() {
+        return vec![];
+    }
+
+    // Tabs are kept, so the caret still lines up
+    let padding = text
+        .chars()
+        .take(column - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+
+    let gutter = " ".repeat(line.to_string().len());
+
+    return vec![format!("{line} | {text}"), format!("{gutter} | {padding}^")];
+}
@@ -1,13 +1,23 @@
+use std::{cell::RefCell, fmt, fs, rc::Rc};
+
 use crate::{
-    interpreter::Interpreter,
+    interpreter::{self, Interpreter},
     lox_class::LoxClass,
     lox_function::LoxFunction,
-    runtime_value::{RuntimeResult, RuntimeValue},
+    lox_map::LoxMap,
+    random::Random,
+    runtime_value::{whole_number, RuntimeError, RuntimeResult, RuntimeValue},
     string::LoxStr,
 };
 
 pub trait LoxCall {
     fn arity(&self) -> usize;
+
+    // Whether a call can pass `argc` arguments
+    fn accepts(&self, argc: usize) -> bool {
+        return argc == self.arity();
+    }
+
     fn call(
         &mut self,
         interpreter: &mut Interpreter,
@@ -21,6 +31,94 @@ pub enum LoxCallable {
     LoxFunction(LoxFunction),
     LoxClass(LoxClass),
     Clock(Clock),
+    ClockMillis(ClockMillis),
+    ClockNanos(ClockNanos),
+    DeleteField(DeleteField),
+    ShallowCopy(ShallowCopy),
+    FloorDiv(FloorDiv),
+    ApproxEqual(ApproxEqual),
+    Math(Math),
+    Write(Write),
+    PrintErr(PrintErr),
+    PrintWith(PrintWith),
+    ReadFile(ReadFile),
+    WriteFile(WriteFile),
+    Fields(Fields),
+    Methods(Methods),
+    AssertType(AssertType),
+    RandomNumber(RandomNumber),
+    RandomInt(RandomInt),
+    Seed(Seed),
+    ToUpper(ToUpper),
+    ToLower(ToLower),
+    Trim(Trim),
+    Contains(Contains),
+    Split(Split),
+    NewMap(NewMap),
+    MapGet(MapGet),
+    MapSet(MapSet),
+    Keys(Keys),
+    Values(Values),
+    Freeze(Freeze),
+    Hex(Hex),
+    Bin(Bin),
+    Apply(Apply),
+    CallByName(CallByName),
+    Defined(Defined),
+    Globals(Globals),
+    Range(Range),
+    ToBool(ToBool),
+    ToInt(ToInt),
+    ToFloat(ToFloat),
+    Captures(Captures),
+    Join(Join),
+    MapList(MapList),
+    FilterList(FilterList),
+    PrimitiveMethod(PrimitiveMethod),
+    NativeFn(NativeFn),
+}
+
+impl LoxCallable {
+    pub fn is_native(&self) -> bool {
+        return !matches!(self, Self::LoxFunction(_) | Self::LoxClass(_));
+    }
+
+    // Names the callee for diagnostics, eg. "Function 'foo'"
+    pub fn describe(&self) -> LoxStr {
+        return match self {
+            Self::LoxFunction(function) => {
+                format!("Function '{}'", function.declaration.name.lexeme).into()
+            }
+            Self::LoxClass(class) => format!("Class '{}'", class.name).into(),
+            native => format!("Native function {}", native.to_string()).into(),
+        };
+    }
+
+    // How many arguments a call takes, eg. "1 argument" or "1 to 3 arguments"
+    pub fn expected_arguments(&self) -> LoxStr {
+        let required = match self {
+            Self::LoxFunction(function) => function.required_arity(),
+            Self::LoxClass(class) => class.required_arity(),
+            Self::Range(range) => range.required_arity(),
+            Self::ApproxEqual(approx_equal) => approx_equal.required_arity(),
+            _ => self.arity(),
+        };
+
+        if required == self.arity() {
+            return count_arguments(required);
+        }
+
+        return format!("{required} to {} arguments", self.arity()).into();
+    }
+}
+
+// eg. "1 argument" or "2 arguments"
+pub fn count_arguments(count: usize) -> LoxStr {
+    if count == 1 {
+        return "1 argument".into();
+    }
+
+    return format!("{count} arguments").into();
 }
 
 impl LoxCall for LoxCallable {
@@ -29,6 +127,61 @@ impl LoxCall for LoxCallable {
             Self::LoxFunction(function) => function.arity(),
             Self::LoxClass(class) => class.arity(),
             Self::Clock(clock) => clock.arity(),
+            Self::ClockMillis(clock_millis) => clock_millis.arity(),
+            Self::ClockNanos(clock_nanos) => clock_nanos.arity(),
+            Self::DeleteField(delete_field) => delete_field.arity(),
+            Self::ShallowCopy(copy) => copy.arity(),
+            Self::FloorDiv(floor_div) => floor_div.arity(),
+            Self::ApproxEqual(approx_equal) => approx_equal.arity(),
+            Self::Math(math) => math.arity(),
+            Self::Write(write) => write.arity(),
+            Self::PrintErr(print_err) => print_err.arity(),
+            Self::PrintWith(print_with) => print_with.arity(),
+            Self::ReadFile(read_file) => read_file.arity(),
+            Self::WriteFile(write_file) => write_file.arity(),
+            Self::Fields(fields) => fields.arity(),
+            Self::Methods(methods) => methods.arity(),
+            Self::AssertType(assert_type) => assert_type.arity(),
+            Self::RandomNumber(random) => random.arity(),
+            Self::RandomInt(random_int) => random_int.arity(),
+            Self::Seed(seed) => seed.arity(),
+            Self::ToUpper(to_upper) => to_upper.arity(),
+            Self::ToLower(to_lower) => to_lower.arity(),
+            Self::Trim(trim) => trim.arity(),
+            Self::Contains(contains) => contains.arity(),
+            Self::Split(split) => split.arity(),
+            Self::NewMap(new_map) => new_map.arity(),
+            Self::MapGet(map_get) => map_get.arity(),
+            Self::MapSet(map_set) => map_set.arity(),
+            Self::Keys(keys) => keys.arity(),
+            Self::Values(values) => values.arity(),
+            Self::Freeze(freeze) => freeze.arity(),
+            Self::Hex(hex) => hex.arity(),
+            Self::Bin(bin) => bin.arity(),
+            Self::Apply(apply) => apply.arity(),
+            Self::CallByName(call) => call.arity(),
+            Self::Defined(defined) => defined.arity(),
+            Self::Globals(globals) => globals.arity(),
+            Self::Range(range) => range.arity(),
+            Self::ToBool(to_bool) => to_bool.arity(),
+            Self::ToInt(to_int) => to_int.arity(),
+            Self::ToFloat(to_float) => to_float.arity(),
+            Self::Captures(captures) => captures.arity(),
+            Self::Join(join) => join.arity(),
+            Self::MapList(map_list) => map_list.arity(),
+            Self::FilterList(filter_list) => filter_list.arity(),
+            Self::PrimitiveMethod(method) => method.arity(),
+            Self::NativeFn(native) => native.arity(),
+        };
+    }
+
+    fn accepts(&self, argc: usize) -> bool {
+        return match self {
+            Self::LoxFunction(function) => function.accepts(argc),
+            Self::LoxClass(class) => class.accepts(argc),
+            Self::Range(range) => range.accepts(argc),
+            Self::ApproxEqual(approx_equal) => approx_equal.accepts(argc),
+            native => argc == native.arity(),
         };
     }
 
@@ -41,6 +194,51 @@ impl LoxCall for LoxCallable {
             Self::LoxFunction(function) => function.call(interpreter, arguments),
             Self::LoxClass(class) => class.call(interpreter, arguments),
             Self::Clock(clock) => clock.call(interpreter, arguments),
+            Self::ClockMillis(clock_millis) => clock_millis.call(interpreter, arguments),
+            Self::ClockNanos(clock_nanos) => clock_nanos.call(interpreter, arguments),
+            Self::DeleteField(delete_field) => delete_field.call(interpreter, arguments),
+            Self::ShallowCopy(copy) => copy.call(interpreter, arguments),
+            Self::FloorDiv(floor_div) => floor_div.call(interpreter, arguments),
+            Self::ApproxEqual(approx_equal) => approx_equal.call(interpreter, arguments),
+            Self::Math(math) => math.call(interpreter, arguments),
+            Self::Write(write) => write.call(interpreter, arguments),
+            Self::PrintErr(print_err) => print_err.call(interpreter, arguments),
+            Self::PrintWith(print_with) => print_with.call(interpreter, arguments),
+            Self::ReadFile(read_file) => read_file.call(interpreter, arguments),
+            Self::WriteFile(write_file) => write_file.call(interpreter, arguments),
+            Self::Fields(fields) => fields.call(interpreter, arguments),
+            Self::Methods(methods) => methods.call(interpreter, arguments),
+            Self::AssertType(assert_type) => assert_type.call(interpreter, arguments),
+            Self::RandomNumber(random) => random.call(interpreter, arguments),
+            Self::RandomInt(random_int) => random_int.call(interpreter, arguments),
+            Self::Seed(seed) => seed.call(interpreter, arguments),
+            Self::ToUpper(to_upper) => to_upper.call(interpreter, arguments),
+            Self::ToLower(to_lower) => to_lower.call(interpreter, arguments),
+            Self::Trim(trim) => trim.call(interpreter, arguments),
+            Self::Contains(contains) => contains.call(interpreter, arguments),
+            Self::Split(split) => split.call(interpreter, arguments),
+            Self::NewMap(new_map) => new_map.call(interpreter, arguments),
+            Self::MapGet(map_get) => map_get.call(interpreter, arguments),
+            Self::MapSet(map_set) => map_set.call(interpreter, arguments),
+            Self::Keys(keys) => keys.call(interpreter, arguments),
+            Self::Values(values) => values.call(interpreter, arguments),
+            Self::Freeze(freeze) => freeze.call(interpreter, arguments),
+            Self::Hex(hex) => hex.call(interpreter, arguments),
+            Self::Bin(bin) => bin.call(interpreter, arguments),
+            Self::Apply(apply) => apply.call(interpreter, arguments),
+            Self::CallByName(call) => call.call(interpreter, arguments),
+            Self::Defined(defined) => defined.call(interpreter, arguments),
+            Self::Globals(globals) => globals.call(interpreter, arguments),
+            Self::Range(range) => range.call(interpreter, arguments),
+            Self::ToBool(to_bool) => to_bool.call(interpreter, arguments),
+            Self::ToInt(to_int) => to_int.call(interpreter, arguments),
+            Self::ToFloat(to_float) => to_float.call(interpreter, arguments),
+            Self::Captures(captures) => captures.call(interpreter, arguments),
+            Self::Join(join) => join.call(interpreter, arguments),
+            Self::MapList(map_list) => map_list.call(interpreter, arguments),
+            Self::FilterList(filter_list) => filter_list.call(interpreter, arguments),
+            Self::PrimitiveMethod(method) => method.call(interpreter, arguments),
+            Self::NativeFn(native) => native.call(interpreter, arguments),
         };
     }
 
@@ -49,6 +247,51 @@ impl LoxCall for LoxCallable {
             Self::LoxFunction(function) => function.to_string(),
             Self::LoxClass(class) => class.to_string(),
             Self::Clock(clock) => clock.to_string(),
+            Self::ClockMillis(clock_millis) => clock_millis.to_string(),
+            Self::ClockNanos(clock_nanos) => clock_nanos.to_string(),
+            Self::DeleteField(delete_field) => delete_field.to_string(),
+            Self::ShallowCopy(copy) => copy.to_string(),
+            Self::FloorDiv(floor_div) => floor_div.to_string(),
+            Self::ApproxEqual(approx_equal) => approx_equal.to_string(),
+            Self::Math(math) => math.to_string(),
+            Self::Write(write) => write.to_string(),
+            Self::PrintErr(print_err) => print_err.to_string(),
+            Self::PrintWith(print_with) => print_with.to_string(),
+            Self::ReadFile(read_file) => read_file.to_string(),
+            Self::WriteFile(write_file) => write_file.to_string(),
+            Self::Fields(fields) => fields.to_string(),
+            Self::Methods(methods) => methods.to_string(),
+            Self::AssertType(assert_type) => assert_type.to_string(),
+            Self::RandomNumber(random) => random.to_string(),
+            Self::RandomInt(random_int) => random_int.to_string(),
+            Self::Seed(seed) => seed.to_string(),
+            Self::ToUpper(to_upper) => to_upper.to_string(),
+            Self::ToLower(to_lower) => to_lower.to_string(),
+            Self::Trim(trim) => trim.to_string(),
+            Self::Contains(contains) => contains.to_string(),
+            Self::Split(split) => split.to_string(),
+            Self::NewMap(new_map) => new_map.to_string(),
+            Self::MapGet(map_get) => map_get.to_string(),
+            Self::MapSet(map_set) => map_set.to_string(),
+            Self::Keys(keys) => keys.to_string(),
+            Self::Values(values) => values.to_string(),
+            Self::Freeze(freeze) => freeze.to_string(),
+            Self::Hex(hex) => hex.to_string(),
+            Self::Bin(bin) => bin.to_string(),
+            Self::Apply(apply) => apply.to_string(),
+            Self::CallByName(call) => call.to_string(),
+            Self::Defined(defined) => defined.to_string(),
+            Self::Globals(globals) => globals.to_string(),
+            Self::Range(range) => range.to_string(),
+            Self::ToBool(to_bool) => to_bool.to_string(),
+            Self::ToInt(to_int) => to_int.to_string(),
+            Self::ToFloat(to_float) => to_float.to_string(),
+            Self::Captures(captures) => captures.to_string(),
+            Self::Join(join) => join.to_string(),
+            Self::MapList(map_list) => map_list.to_string(),
+            Self::FilterList(filter_list) => filter_list.to_string(),
+            Self::PrimitiveMethod(method) => method.to_string(),
+            Self::NativeFn(native) => native.to_string(),
         };
     }
 }
@@ -74,3 +317,1539 @@ impl LoxCall for Clock {
         return "<fn clock>".into();
     }
 }
+
+// Unlike `clock`, these count from when the interpreter started, and never go backwards
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClockMillis;
+impl LoxCall for ClockMillis {
+    fn arity(&self) -> usize {
+        return 0;
+    }
+
+    fn call(&mut self, interpreter: &mut Interpreter, _: Vec<RuntimeValue>) -> RuntimeResult {
+        let elapsed = interpreter.start.elapsed();
+
+        return Ok(RuntimeValue::Number(elapsed.as_millis() as f64));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn clockMillis>".into();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClockNanos;
+impl LoxCall for ClockNanos {
+    fn arity(&self) -> usize {
+        return 0;
+    }
+
+    fn call(&mut self, interpreter: &mut Interpreter, _: Vec<RuntimeValue>) -> RuntimeResult {
+        let elapsed = interpreter.start.elapsed();
+
+        return Ok(RuntimeValue::Number(elapsed.as_nanos() as f64));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn clockNanos>".into();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeleteField;
+impl LoxCall for DeleteField {
+    fn arity(&self) -> usize {
+        return 2;
+    }
+
+    fn call(&mut self, _: &mut Interpreter, mut arguments: Vec<RuntimeValue>) -> RuntimeResult {
+        let name = arguments.pop().unwrap();
+        let instance = arguments.pop().unwrap();
+
+        let RuntimeValue::LoxInstance(mut instance) = instance else {
+            return Err(invalid_argument("deleteField", "an instance", &instance));
+        };
+
+        let RuntimeValue::String(name) = name else {
+            return Err(invalid_argument("deleteField", "a field name", &name));
+        };
+
+        return Ok(RuntimeValue::Boolean(instance.remove(&name)?));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn deleteField>".into();
+    }
+}
+
+// Shallow copy, so mutating the copy's fields / elements doesn't affect the original.
+// Other values are immutable (or callables), so they're returned as-is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShallowCopy;
+impl LoxCall for ShallowCopy {
+    fn arity(&self) -> usize {
+        return 1;
+    }
+
+    fn call(&mut self, _: &mut Interpreter, mut arguments: Vec<RuntimeValue>) -> RuntimeResult {
+        return match arguments.pop().unwrap() {
+            RuntimeValue::LoxInstance(instance) => Ok(RuntimeValue::LoxInstance(instance.copy())),
+            RuntimeValue::List(values) => Ok(RuntimeValue::list(values.borrow().clone())),
+            RuntimeValue::Map(map) => Ok(RuntimeValue::map(map.borrow().clone())),
+            value => Ok(value),
+        };
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn copy>".into();
+    }
+}
+
+// Integer division rounding towards negative infinity, so `floorDiv(-7, 2)` is -4.
+// A native rather than a `//` operator, since `//` starts a comment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FloorDiv;
+impl LoxCall for FloorDiv {
+    fn arity(&self) -> usize {
+        return 2;
+    }
+
+    fn call(&mut self, _: &mut Interpreter, mut arguments: Vec<RuntimeValue>) -> RuntimeResult {
+        let right = arguments.pop().unwrap();
+        let left = arguments.pop().unwrap();
+
+        let (RuntimeValue::Number(left), RuntimeValue::Number(right)) = (&left, &right) else {
+            let value = if let RuntimeValue::Number(_) = left {
+                &right
+            } else {
+                &left
+            };
+            return Err(invalid_argument("floorDiv", "a number", value));
+        };
+
+        if *right == 0.0 {
+            return Err(RuntimeError::DivisionByZero {
+                callee: "floorDiv".into(),
+            });
+        }
+
+        return Ok(RuntimeValue::Number((left / right).floor()));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn floorDiv>".into();
+    }
+}
+
+// Whether 2 numbers are within `eps` of each other, for comparing computed floats where
+// `==` is too strict, eg. `approxEqual(0.1 + 0.2, 0.3)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApproxEqual;
+impl ApproxEqual {
+    const DEFAULT_EPS: f64 = 0.000000001;
+
+    pub fn required_arity(&self) -> usize {
+        return 2;
+    }
+}
+
+impl LoxCall for ApproxEqual {
+    fn arity(&self) -> usize {
+        return 3;
+    }
+
+    fn accepts(&self, argc: usize) -> bool {
+        return (self.required_arity()..=self.arity()).contains(&argc);
+    }
+
+    fn call(&mut self, _: &mut Interpreter, arguments: Vec<RuntimeValue>) -> RuntimeResult {
+        let numbers = arguments
+            .into_iter()
+            .map(|argument| number_argument("approxEqual", argument))
+            .collect::<RuntimeResult<Vec<f64>>>()?;
+
+        let (a, b) = (numbers[0], numbers[1]);
+        let eps = numbers.get(2).copied().unwrap_or(Self::DEFAULT_EPS);
+
+        return Ok(RuntimeValue::Boolean((a - b).abs() <= eps));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn approxEqual>".into();
+    }
+}
+
+fn number_argument(callee: &str, value: RuntimeValue) -> RuntimeResult<f64> {
+    let RuntimeValue::Number(number) = value else {
+        return Err(RuntimeError::NotANumber {
+            callee: callee.into(),
+            found: value.described_type().into(),
+        });
+    };
+
+    return Ok(number);
+}
+
+// The numeric helpers, each registered under the name `to_string` gives it
+#[derive(Debug, Clone, PartialEq)]
+pub enum Math {
+    Abs,
+    // -1, 0 or 1, with -0 counting as 0 and NaN staying NaN
+    Sign,
+    // Halves round away from zero, so `round(2.5)` is 3 and `round(-2.5)` is -3
+    Round,
+    // Drops the fraction, rounding towards zero
+    Trunc,
+    Min,
+    Max,
+    IsNaN,
+    // Either infinity, but not NaN
+    IsInfinite,
+}
+
+impl Math {
+    pub const ALL: [Math; 8] = [
+        Self::Abs,
+        Self::Sign,
+        Self::Round,
+        Self::Trunc,
+        Self::Min,
+        Self::Max,
+        Self::IsNaN,
+        Self::IsInfinite,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Self::Abs => "abs",
+            Self::Sign => "sign",
+            Self::Round => "round",
+            Self::Trunc => "trunc",
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::IsNaN => "isNaN",
+            Self::IsInfinite => "isInfinite",
+        };
+    }
+}
+
+impl LoxCall for Math {
+    fn arity(&self) -> usize {
+        return match self {
+            Self::Min | Self::Max => 2,
+            _ => 1,
+        };
+    }
+
+    fn call(&mut self, _: &mut Interpreter, arguments: Vec<RuntimeValue>) -> RuntimeResult {
+        let numbers = arguments
+            .into_iter()
+            .map(|argument| number_argument(self.name(), argument))
+            .collect::<RuntimeResult<Vec<f64>>>()?;
+
+        let result = match self {
+            Self::Abs => numbers[0].abs(),
+            Self::Sign if numbers[0] == 0.0 => 0.0,
+            Self::Sign => numbers[0].signum(),
+            Self::Round => numbers[0].round(),
+            Self::Trunc => numbers[0].trunc(),
+            Self::Min => numbers[0].min(numbers[1]),
+            Self::Max => numbers[0].max(numbers[1]),
+            Self::IsNaN => return Ok(RuntimeValue::Boolean(numbers[0].is_nan())),
+            Self::IsInfinite => return Ok(RuntimeValue::Boolean(numbers[0].is_infinite())),
+        };
+
+        return Ok(RuntimeValue::Number(result));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return format!("<fn {}>", self.name()).into();
+    }
+}
+
+// Like `print`, but without the trailing newline, eg. for prompts
+#[derive(Debug, Clone, PartialEq)]
+pub struct Write;
+impl LoxCall for Write {
+    fn arity(&self) -> usize {
+        return 1;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let text = interpreter.stringify(&arguments[0]);
+        interpreter.write(&text);
+
+        return Ok(RuntimeValue::Nil);
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn write>".into();
+    }
+}
+
+// Like `print`, but to the error output, so diagnostics can be told apart from the output
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrintErr;
+impl LoxCall for PrintErr {
+    fn arity(&self) -> usize {
+        return 1;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let text = interpreter.stringify(&arguments[0]);
+        interpreter.write_err(&format!("{text}\n"));
+
+        return Ok(RuntimeValue::Nil);
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn printErr>".into();
+    }
+}
+
+// Prints the list's elements with `sep` between them and `end` after, since there are
+// no keyword arguments, eg. `printWith(", ", "!", [1, 2])` prints "1, 2!" with no newline
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrintWith;
+impl LoxCall for PrintWith {
+    fn arity(&self) -> usize {
+        return 3;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let values = list_argument("printWith", arguments.pop().unwrap())?;
+        let end = string_argument("printWith", arguments.pop().unwrap())?;
+        let sep = string_argument("printWith", arguments.pop().unwrap())?;
+
+        let mut text = values
+            .iter()
+            .map(|value| interpreter.stringify(value).to_string())
+            .collect::<Vec<String>>()
+            .join(&sep);
+        text.push_str(&end);
+
+        interpreter.write(&text);
+
+        return Ok(RuntimeValue::Nil);
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn printWith>".into();
+    }
+}
+
+// The whole of a UTF-8 text file. Like imports, a relative path is from the script's directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadFile;
+impl LoxCall for ReadFile {
+    fn arity(&self) -> usize {
+        return 1;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let path = string_argument("readFile", arguments.pop().unwrap())?;
+
+        let text = fs::read_to_string(interpreter.script_relative_path(&path))
+            .map_err(|e| file_error("read", &path, e))?;
+
+        interpreter.check_string_length(&text)?;
+
+        return Ok(RuntimeValue::String(text.into()));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn readFile>".into();
+    }
+}
+
+// Replaces the file's contents with the string, creating it if need be
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteFile;
+impl LoxCall for WriteFile {
+    fn arity(&self) -> usize {
+        return 2;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let text = string_argument("writeFile", arguments.pop().unwrap())?;
+        let path = string_argument("writeFile", arguments.pop().unwrap())?;
+
+        fs::write(interpreter.script_relative_path(&path), &*text)
+            .map_err(|e| file_error("write", &path, e))?;
+
+        return Ok(RuntimeValue::Nil);
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn writeFile>".into();
+    }
+}
+
+fn file_error(action: &str, path: &str, error: std::io::Error) -> RuntimeError {
+    return RuntimeError::FileAccess {
+        action: action.into(),
+        path: path.into(),
+        details: io_error_details(&error),
+    };
+}
+
+// The same on every OS for the usual failures, rather than the OS's own wording and code
+pub fn io_error_details(error: &std::io::Error) -> String {
+    return match error.kind() {
+        std::io::ErrorKind::NotFound => "No such file".to_string(),
+        std::io::ErrorKind::PermissionDenied => "Permission denied".to_string(),
+        std::io::ErrorKind::IsADirectory => "Is a directory".to_string(),
+        _ => error.to_string(),
+    };
+}
+
+// The names of an instance's fields, in the order they were first set
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fields;
+impl LoxCall for Fields {
+    fn arity(&self) -> usize {
+        return 1;
+    }
+
+    fn call(&mut self, _: &mut Interpreter, mut arguments: Vec<RuntimeValue>) -> RuntimeResult {
+        let value = arguments.pop().unwrap();
+
+        let RuntimeValue::LoxInstance(instance) = value else {
+            return Err(invalid_argument("fields", "an instance", &value));
+        };
+
+        let names = instance
+            .fields
+            .borrow()
+            .keys()
+            .map(|name| RuntimeValue::String(name.clone()))
+            .collect();
+
+        return Ok(RuntimeValue::list(names));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn fields>".into();
+    }
+}
+
+// The names of a class's methods, in declaration order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Methods;
+impl LoxCall for Methods {
+    fn arity(&self) -> usize {
+        return 1;
+    }
+
+    fn call(&mut self, _: &mut Interpreter, mut arguments: Vec<RuntimeValue>) -> RuntimeResult {
+        let value = arguments.pop().unwrap();
+
+        let RuntimeValue::LoxCallable(LoxCallable::LoxClass(class)) = value else {
+            return Err(invalid_argument("methods", "a class", &value));
+        };
+
+        let names = class
+            .methods
+            .borrow()
+            .keys()
+            .map(|name| RuntimeValue::String(name.clone()))
+            .collect();
+
+        return Ok(RuntimeValue::list(names));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn methods>".into();
+    }
+}
+
+// Returns the value if it has the named type, so it can be checked inline
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssertType;
+impl LoxCall for AssertType {
+    fn arity(&self) -> usize {
+        return 2;
+    }
+
+    fn call(&mut self, _: &mut Interpreter, mut arguments: Vec<RuntimeValue>) -> RuntimeResult {
+        let type_name = arguments.pop().unwrap();
+        let value = arguments.pop().unwrap();
+
+        let RuntimeValue::String(expected) = type_name else {
+            return Err(invalid_argument("assertType", "a type name", &type_name));
+        };
+
+        if !RuntimeValue::TYPE_NAMES.contains(&&*expected) {
+            return Err(RuntimeError::UnknownTypeName { name: expected });
+        }
+
+        if value.type_name() != &*expected {
+            return Err(RuntimeError::TypeAssertion {
+                expected,
+                found: value.type_name().into(),
+            });
+        }
+
+        return Ok(value);
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn assertType>".into();
+    }
+}
+
+// Named so it doesn't clash with the generator itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct RandomNumber;
+impl LoxCall for RandomNumber {
+    fn arity(&self) -> usize {
+        return 0;
+    }
+
+    fn call(&mut self, interpreter: &mut Interpreter, _: Vec<RuntimeValue>) -> RuntimeResult {
+        return Ok(RuntimeValue::Number(interpreter.random.next_f64()));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn random>".into();
+    }
+}
+
+// A whole number between `lo` and `hi`, inclusive
+#[derive(Debug, Clone, PartialEq)]
+pub struct RandomInt;
+impl LoxCall for RandomInt {
+    fn arity(&self) -> usize {
+        return 2;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let hi = arguments.pop().unwrap();
+        let lo = arguments.pop().unwrap();
+
+        for bound in [&lo, &hi] {
+            let RuntimeValue::Number(number) = bound else {
+                return Err(invalid_argument("randomInt", "a number", bound));
+            };
+
+            if number.fract() != 0.0 {
+                return Err(RuntimeError::NotWholeNumber {
+                    callee: "randomInt".into(),
+                    found: interpreter.stringify(bound),
+                });
+            }
+        }
+
+        let (RuntimeValue::Number(lo), RuntimeValue::Number(hi)) = (lo, hi) else {
+            unreachable!("Bounds were checked to be numbers");
+        };
+
+        if lo > hi {
+            return Err(RuntimeError::ReversedBounds {
+                callee: "randomInt".into(),
+            });
+        }
+
+        let offset = (interpreter.random.next_f64() * (hi - lo + 1.0)).floor();
+
+        return Ok(RuntimeValue::Number(lo + offset));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn randomInt>".into();
+    }
+}
+
+// Reseeds `random` / `randomInt` so a run can be reproduced
+#[derive(Debug, Clone, PartialEq)]
+pub struct Seed;
+impl LoxCall for Seed {
+    fn arity(&self) -> usize {
+        return 1;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let value = arguments.pop().unwrap();
+
+        let RuntimeValue::Number(seed) = value else {
+            return Err(invalid_argument("seed", "a number", &value));
+        };
+
+        interpreter.random = Random::seeded(seed.to_bits());
+
+        return Ok(RuntimeValue::Nil);
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn seed>".into();
+    }
+}
+
+// For an argument of the wrong type, naming the type rather than showing the value, which
+// for a function or instance could take a while
+pub fn invalid_argument(callee: &str, expected: &str, value: &RuntimeValue) -> RuntimeError {
+    return RuntimeError::InvalidArgument {
+        callee: callee.into(),
+        expected: expected.into(),
+        found: value.described_type().into(),
+    };
+}
+
+fn string_argument(callee: &str, value: RuntimeValue) -> RuntimeResult<LoxStr> {
+    let RuntimeValue::String(value) = value else {
+        return Err(invalid_argument(callee, "a string", &value));
+    };
+
+    return Ok(value);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToUpper;
+impl LoxCall for ToUpper {
+    fn arity(&self) -> usize {
+        return 1;
+    }
+
+    fn call(&mut self, _: &mut Interpreter, mut arguments: Vec<RuntimeValue>) -> RuntimeResult {
+        let text = string_argument("toUpper", arguments.pop().unwrap())?;
+
+        return Ok(RuntimeValue::String(text.to_uppercase().into()));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn toUpper>".into();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToLower;
+impl LoxCall for ToLower {
+    fn arity(&self) -> usize {
+        return 1;
+    }
+
+    fn call(&mut self, _: &mut Interpreter, mut arguments: Vec<RuntimeValue>) -> RuntimeResult {
+        let text = string_argument("toLower", arguments.pop().unwrap())?;
+
+        return Ok(RuntimeValue::String(text.to_lowercase().into()));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn toLower>".into();
+    }
+}
+
+// Strips whitespace from both ends
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trim;
+impl LoxCall for Trim {
+    fn arity(&self) -> usize {
+        return 1;
+    }
+
+    fn call(&mut self, _: &mut Interpreter, mut arguments: Vec<RuntimeValue>) -> RuntimeResult {
+        let text = string_argument("trim", arguments.pop().unwrap())?;
+
+        return Ok(RuntimeValue::String(text.trim().into()));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn trim>".into();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contains;
+impl LoxCall for Contains {
+    fn arity(&self) -> usize {
+        return 2;
+    }
+
+    fn call(&mut self, _: &mut Interpreter, mut arguments: Vec<RuntimeValue>) -> RuntimeResult {
+        let needle = string_argument("contains", arguments.pop().unwrap())?;
+        let text = string_argument("contains", arguments.pop().unwrap())?;
+
+        return Ok(RuntimeValue::Boolean(text.contains(&*needle)));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn contains>".into();
+    }
+}
+
+// An empty delimiter splits the string into its characters
+#[derive(Debug, Clone, PartialEq)]
+pub struct Split;
+impl LoxCall for Split {
+    fn arity(&self) -> usize {
+        return 2;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let delimiter = string_argument("split", arguments.pop().unwrap())?;
+        let text = string_argument("split", arguments.pop().unwrap())?;
+
+        let parts = if delimiter.is_empty() {
+            text.chars()
+                .map(|c| RuntimeValue::String(c.to_string().into()))
+                .collect()
+        } else {
+            text.split(&*delimiter)
+                .map(|part| RuntimeValue::String(part.into()))
+                .collect::<Vec<RuntimeValue>>()
+        };
+
+        interpreter.check_list_length(parts.len())?;
+        return Ok(RuntimeValue::list(parts));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn split>".into();
+    }
+}
+
+fn map_argument(callee: &str, value: RuntimeValue) -> RuntimeResult<Rc<RefCell<LoxMap>>> {
+    let RuntimeValue::Map(map) = value else {
+        return Err(invalid_argument(callee, "a map", &value));
+    };
+
+    return Ok(map);
+}
+
+// An empty map, filled in with `mapSet`
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewMap;
+impl LoxCall for NewMap {
+    fn arity(&self) -> usize {
+        return 0;
+    }
+
+    fn call(&mut self, _: &mut Interpreter, _: Vec<RuntimeValue>) -> RuntimeResult {
+        return Ok(RuntimeValue::map(LoxMap::new()));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn map>".into();
+    }
+}
+
+// A missing key gives `nil`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapGet;
+impl LoxCall for MapGet {
+    fn arity(&self) -> usize {
+        return 2;
+    }
+
+    fn call(&mut self, _: &mut Interpreter, mut arguments: Vec<RuntimeValue>) -> RuntimeResult {
+        let key = arguments.pop().unwrap();
+        let map = map_argument("mapGet", arguments.pop().unwrap())?;
+
+        let value = map.borrow().get(&key)?;
+        return Ok(value.unwrap_or(RuntimeValue::Nil));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn mapGet>".into();
+    }
+}
+
+// Returns the value, like assignment does
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapSet;
+impl LoxCall for MapSet {
+    fn arity(&self) -> usize {
+        return 3;
+    }
+
+    fn call(&mut self, _: &mut Interpreter, mut arguments: Vec<RuntimeValue>) -> RuntimeResult {
+        let value = arguments.pop().unwrap();
+        let key = arguments.pop().unwrap();
+        let map = map_argument("mapSet", arguments.pop().unwrap())?;
+
+        map.borrow_mut().insert(key, value.clone())?;
+        return Ok(value);
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn mapSet>".into();
+    }
+}
+
+// In insertion order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keys;
+impl LoxCall for Keys {
+    fn arity(&self) -> usize {
+        return 1;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let map = map_argument("keys", arguments.pop().unwrap())?;
+
+        let keys = map.borrow().keys();
+        interpreter.check_list_length(keys.len())?;
+        return Ok(RuntimeValue::list(keys));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn keys>".into();
+    }
+}
+
+// In the same order as `keys`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Values;
+impl LoxCall for Values {
+    fn arity(&self) -> usize {
+        return 1;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let map = map_argument("values", arguments.pop().unwrap())?;
+
+        let values = map.borrow().values();
+        interpreter.check_list_length(values.len())?;
+        return Ok(RuntimeValue::list(values));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn values>".into();
+    }
+}
+
+// Stops any more fields being set or deleted. Returns the instance, so it can wrap a
+// constructor call, eg. `var origin = freeze(Point(0, 0));`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Freeze;
+impl LoxCall for Freeze {
+    fn arity(&self) -> usize {
+        return 1;
+    }
+
+    fn call(&mut self, _: &mut Interpreter, mut arguments: Vec<RuntimeValue>) -> RuntimeResult {
+        let value = arguments.pop().unwrap();
+
+        let RuntimeValue::LoxInstance(instance) = value else {
+            return Err(invalid_argument("freeze", "an instance", &value));
+        };
+
+        instance.freeze();
+        return Ok(RuntimeValue::LoxInstance(instance));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn freeze>".into();
+    }
+}
+
+fn whole_number_argument(
+    interpreter: &Interpreter,
+    callee: &str,
+    value: RuntimeValue,
+) -> RuntimeResult<i64> {
+    let RuntimeValue::Number(number) = value else {
+        return Err(invalid_argument(callee, "a number", &value));
+    };
+
+    let Some(number) = whole_number(number) else {
+        return Err(RuntimeError::NotWholeNumber {
+            callee: callee.into(),
+            found: interpreter.stringify(&value),
+        });
+    };
+
+    return Ok(number);
+}
+
+// Lowercase digits, with the sign before the prefix, eg. `hex(-255)` is "-0xff"
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hex;
+impl LoxCall for Hex {
+    fn arity(&self) -> usize {
+        return 1;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let number = whole_number_argument(interpreter, "hex", arguments.pop().unwrap())?;
+
+        let sign = if number < 0 { "-" } else { "" };
+        return Ok(RuntimeValue::String(
+            format!("{sign}0x{:x}", number.unsigned_abs()).into(),
+        ));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn hex>".into();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bin;
+impl LoxCall for Bin {
+    fn arity(&self) -> usize {
+        return 1;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let number = whole_number_argument(interpreter, "bin", arguments.pop().unwrap())?;
+
+        let sign = if number < 0 { "-" } else { "" };
+        return Ok(RuntimeValue::String(
+            format!("{sign}0b{:b}", number.unsigned_abs()).into(),
+        ));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn bin>".into();
+    }
+}
+
+// Calls a function with the elements of a list as its arguments, eg. `apply(add, list)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Apply;
+impl LoxCall for Apply {
+    fn arity(&self) -> usize {
+        return 2;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let list = arguments.pop().unwrap();
+        let callee = arguments.pop().unwrap();
+
+        let RuntimeValue::LoxCallable(mut function) = callee else {
+            return Err(RuntimeError::InvalidCallable {
+                value: callee,
+                details: Some("Can only apply functions and classes".to_string()),
+            });
+        };
+
+        return call_with_list(interpreter, "apply", &mut function, list);
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn apply>".into();
+    }
+}
+
+fn call_with_list(
+    interpreter: &mut Interpreter,
+    callee: &str,
+    function: &mut LoxCallable,
+    list: RuntimeValue,
+) -> RuntimeResult {
+    let RuntimeValue::List(list) = list else {
+        return Err(invalid_argument(callee, "a list of arguments", &list));
+    };
+
+    let arguments = list.borrow().clone();
+
+    if let Err(expected) = interpreter.check_argument_count(function, arguments.len()) {
+        return Err(RuntimeError::WrongNumberOfListArgs {
+            callee: function.describe(),
+            expected,
+            found: arguments.len(),
+        });
+    }
+
+    return function.call(interpreter, arguments);
+}
+
+// Calls a global function or class by name, eg. `call("add", [1, 2])`.
+// Only sees globals, so a local of the same name doesn't get in the way.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallByName;
+impl LoxCall for CallByName {
+    fn arity(&self) -> usize {
+        return 2;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let list = arguments.pop().unwrap();
+        let name = string_argument("call", arguments.pop().unwrap())?;
+
+        let Some(value) = interpreter.globals.borrow().get_own(&name) else {
+            return Err(RuntimeError::UndefinedGlobal { name });
+        };
+
+        let RuntimeValue::LoxCallable(mut function) = value else {
+            return Err(RuntimeError::NotCallableGlobal { name });
+        };
+
+        return call_with_list(interpreter, "call", &mut function, list);
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn call>".into();
+    }
+}
+
+// Whether there's a global of that name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Defined;
+impl LoxCall for Defined {
+    fn arity(&self) -> usize {
+        return 1;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let name = string_argument("defined", arguments.pop().unwrap())?;
+
+        let defined = interpreter.globals.borrow().get_own(&name).is_some();
+        return Ok(RuntimeValue::Boolean(defined));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn defined>".into();
+    }
+}
+
+// The names of the script's globals, sorted, leaving out the built-in natives and constants
+// like `:vars`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Globals;
+impl LoxCall for Globals {
+    fn arity(&self) -> usize {
+        return 0;
+    }
+
+    fn call(&mut self, interpreter: &mut Interpreter, _: Vec<RuntimeValue>) -> RuntimeResult {
+        let names = interpreter
+            .globals
+            .borrow()
+            .entries()
+            .into_iter()
+            .filter(|(name, value)| !interpreter::is_builtin(name, value))
+            .map(|(name, _)| RuntimeValue::String(name))
+            .collect();
+
+        return Ok(RuntimeValue::list(names));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn globals>".into();
+    }
+}
+
+// `range(start, end)` or `range(start, end, step)`, from `start` up to but not including `end`.
+// A negative step counts down, eg. `range(3, 0, -1)` is `[3, 2, 1]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Range;
+impl Range {
+    pub fn required_arity(&self) -> usize {
+        return 2;
+    }
+}
+
+impl LoxCall for Range {
+    fn arity(&self) -> usize {
+        return 3;
+    }
+
+    fn accepts(&self, argc: usize) -> bool {
+        return (self.required_arity()..=self.arity()).contains(&argc);
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let mut numbers = vec![];
+        for argument in arguments {
+            let RuntimeValue::Number(number) = argument else {
+                return Err(invalid_argument("range", "a number", &argument));
+            };
+
+            if !number.is_finite() {
+                return Err(RuntimeError::InvalidRange {
+                    details: "its arguments must be finite".into(),
+                });
+            }
+
+            numbers.push(number);
+        }
+
+        let (start, end) = (numbers[0], numbers[1]);
+        let step = numbers.get(2).copied().unwrap_or(1.0);
+
+        if step == 0.0 {
+            return Err(RuntimeError::InvalidRange {
+                details: "its step can't be zero".into(),
+            });
+        }
+
+        // Multiplying rather than adding up the steps, so fractional steps don't drift
+        let mut elements = vec![];
+        loop {
+            let value = start + elements.len() as f64 * step;
+
+            if (step > 0.0 && value >= end) || (step < 0.0 && value <= end) {
+                break;
+            }
+
+            // Checked as it grows, so a huge range stops before it uses up memory
+            interpreter.check_list_length(elements.len() + 1)?;
+            elements.push(RuntimeValue::Number(value));
+        }
+
+        return Ok(RuntimeValue::list(elements));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn range>".into();
+    }
+}
+
+// Whether the value is truthy, the same test `if` uses. Only `nil` and `false` are false,
+// so eg. `bool(0)` is true, even with strict conditions
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToBool;
+impl LoxCall for ToBool {
+    fn arity(&self) -> usize {
+        return 1;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let value = arguments.pop().unwrap();
+
+        return Ok(RuntimeValue::Boolean(interpreter.is_truthy(&value)));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn bool>".into();
+    }
+}
+
+// Truncates a number towards zero, or parses a string of digits, eg. `int("-12")`.
+// A string with a fraction isn't an int, so `int("3.9")` fails where `int(3.9)` is 3
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToInt;
+impl LoxCall for ToInt {
+    fn arity(&self) -> usize {
+        return 1;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let value = arguments.pop().unwrap();
+
+        return match &value {
+            RuntimeValue::Number(number) if number.is_finite() => {
+                Ok(RuntimeValue::Number(number.trunc()))
+            }
+            RuntimeValue::String(text) => match text.trim().parse::<i64>() {
+                Ok(number) => Ok(RuntimeValue::Number(number as f64)),
+                Err(_) => Err(conversion_error(interpreter, "int", &value)),
+            },
+            _ => Err(conversion_error(interpreter, "int", &value)),
+        };
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn int>".into();
+    }
+}
+
+// Numbers are returned as they are, while strings are parsed, eg. `float("2.5")`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToFloat;
+impl LoxCall for ToFloat {
+    fn arity(&self) -> usize {
+        return 1;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let value = arguments.pop().unwrap();
+
+        return match &value {
+            RuntimeValue::Number(_) => Ok(value),
+            RuntimeValue::String(text) => match text.trim().parse::<f64>() {
+                Ok(number) => Ok(RuntimeValue::Number(number)),
+                Err(_) => Err(conversion_error(interpreter, "float", &value)),
+            },
+            _ => Err(conversion_error(interpreter, "float", &value)),
+        };
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn float>".into();
+    }
+}
+
+// Strings are quoted so eg. `int("nil")` and `int(nil)` read differently
+fn conversion_error(interpreter: &Interpreter, callee: &str, value: &RuntimeValue) -> RuntimeError {
+    let found = match value {
+        RuntimeValue::String(text) => format!("\"{text}\""),
+        RuntimeValue::Nil | RuntimeValue::Boolean(_) | RuntimeValue::Number(_) => {
+            interpreter.stringify(value).to_string()
+        }
+        value => value.described_type(),
+    };
+
+    return RuntimeError::InvalidConversion {
+        callee: callee.into(),
+        found: found.into(),
+    };
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrimitiveMethodKind {
+    ToString,
+    Length,
+    ToUpper,
+    ToLower,
+    Trim,
+}
+
+// The names of the enclosing local variables a function closes over, eg. `["count"]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Captures;
+impl LoxCall for Captures {
+    fn arity(&self) -> usize {
+        return 1;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let function = arguments.pop().unwrap();
+
+        let RuntimeValue::LoxCallable(LoxCallable::LoxFunction(function)) = function else {
+            return Err(invalid_argument(
+                "captures",
+                "a function declared in Lox",
+                &function,
+            ));
+        };
+
+        let names = interpreter
+            .captured_variables(&function.declaration)
+            .into_iter()
+            .map(RuntimeValue::String)
+            .collect();
+
+        return Ok(RuntimeValue::list(names));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn captures>".into();
+    }
+}
+
+fn list_argument(callee: &str, value: RuntimeValue) -> RuntimeResult<Vec<RuntimeValue>> {
+    let RuntimeValue::List(list) = value else {
+        return Err(invalid_argument(callee, "a list", &value));
+    };
+
+    return Ok(list.borrow().clone());
+}
+
+// Joins the elements as they'd print, eg. `join([1, 2, 3], "-")` is "1-2-3"
+#[derive(Debug, Clone, PartialEq)]
+pub struct Join;
+impl LoxCall for Join {
+    fn arity(&self) -> usize {
+        return 2;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let separator = string_argument("join", arguments.pop().unwrap())?;
+        let list = list_argument("join", arguments.pop().unwrap())?;
+
+        let text = list
+            .iter()
+            .map(|element| interpreter.stringify(element).to_string())
+            .collect::<Vec<String>>()
+            .join(&separator);
+
+        interpreter.check_string_length(&text)?;
+
+        return Ok(RuntimeValue::String(text.into()));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn join>".into();
+    }
+}
+
+// A new list of what `callback` returns for each element
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapList;
+impl LoxCall for MapList {
+    fn arity(&self) -> usize {
+        return 2;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let mut callback = callback_argument(interpreter, "mapList", arguments.pop().unwrap())?;
+        let list = list_argument("mapList", arguments.pop().unwrap())?;
+
+        let mut mapped = vec![];
+        for element in list {
+            mapped.push(callback.call(interpreter, vec![element])?);
+        }
+
+        return Ok(RuntimeValue::list(mapped));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn mapList>".into();
+    }
+}
+
+// A new list of the elements `callback` returns something truthy for
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterList;
+impl LoxCall for FilterList {
+    fn arity(&self) -> usize {
+        return 2;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        mut arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        let mut callback = callback_argument(interpreter, "filterList", arguments.pop().unwrap())?;
+        let list = list_argument("filterList", arguments.pop().unwrap())?;
+
+        let mut filtered = vec![];
+        for element in list {
+            let keep = callback.call(interpreter, vec![element.clone()])?;
+
+            if interpreter.is_truthy(&keep) {
+                filtered.push(element);
+            }
+        }
+
+        return Ok(RuntimeValue::list(filtered));
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return "<fn filterList>".into();
+    }
+}
+
+// A function or class that can be called with just an element
+fn callback_argument(
+    interpreter: &Interpreter,
+    callee: &str,
+    value: RuntimeValue,
+) -> RuntimeResult<LoxCallable> {
+    let RuntimeValue::LoxCallable(callback) = value else {
+        return Err(RuntimeError::InvalidCallback {
+            callee: callee.into(),
+            found: value.described_type().into(),
+        });
+    };
+
+    if let Err(expected) = interpreter.check_argument_count(&callback, 1) {
+        return Err(RuntimeError::CallbackArity {
+            callee: callee.into(),
+            callback: callback.describe(),
+            expected,
+        });
+    }
+
+    return Ok(callback);
+}
+
+pub type NativeClosure = Rc<dyn Fn(&mut Interpreter, Vec<RuntimeValue>) -> RuntimeResult>;
+
+// A native an embedder registers with `Interpreter::define_native`,
+// rather than writing a struct for it like those above
+#[derive(Clone)]
+pub struct NativeFn {
+    pub name: LoxStr,
+    pub arity: usize,
+    pub func: NativeClosure,
+}
+
+impl fmt::Debug for NativeFn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return f
+            .debug_struct("NativeFn")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish_non_exhaustive();
+    }
+}
+
+// Closures can't be compared, so like functions it's only equal to itself
+impl PartialEq for NativeFn {
+    fn eq(&self, other: &Self) -> bool {
+        return Rc::ptr_eq(&self.func, &other.func);
+    }
+}
+
+impl LoxCall for NativeFn {
+    fn arity(&self) -> usize {
+        return self.arity;
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &mut Interpreter,
+        arguments: Vec<RuntimeValue>,
+    ) -> RuntimeResult {
+        return (self.func)(interpreter, arguments);
+    }
+
+    fn to_string(&self) -> LoxStr {
+        return format!("<fn {}>", self.name).into();
+    }
+}
+
+// A built-in method of `nil`, a boolean, number or string, bound to the value it was
+// looked up on, eg. `(3).toString()`. Every one of them has `toString`, and strings
+// also have `length`, `toUpper`, `toLower` and `trim`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrimitiveMethod {
+    receiver: Box<RuntimeValue>,
+    kind: PrimitiveMethodKind,
+}
+
+impl PrimitiveMethod {
+    pub fn find(receiver: &RuntimeValue, name: &str) -> Option<Self> {
+        let kind = match (receiver, name) {
+            (
+                RuntimeValue::Nil
+                | RuntimeValue::Boolean(_)
+                | RuntimeValue::Number(_)
+                | RuntimeValue::String(_),
+                "toString",
+            ) => PrimitiveMethodKind::ToString,
+            (RuntimeValue::String(_), "length") => PrimitiveMethodKind::Length,
+            (RuntimeValue::String(_), "toUpper") => PrimitiveMethodKind::ToUpper,
+            (RuntimeValue::String(_), "toLower") => PrimitiveMethodKind::ToLower,
+            (RuntimeValue::String(_), "trim") => PrimitiveMethodKind::Trim,
+            _ => return None,
+        };
+
+        return Some(Self {
+            receiver: Box::new(receiver.clone()),
+            kind,
+        });
+    }
+
+    pub fn receiver(&self) -> &RuntimeValue {
+        return &self.receiver;
+    }
+}
+
+impl LoxCall for PrimitiveMethod {
+    fn arity(&self) -> usize {
+        return 0;
+    }
+
+    fn call(&mut self, interpreter: &mut Interpreter, _: Vec<RuntimeValue>) -> RuntimeResult {
+        let receiver = vec![*self.receiver.clone()];
+
+        return match self.kind {
+            PrimitiveMethodKind::ToString => {
+                Ok(RuntimeValue::String(interpreter.stringify(&self.receiver)))
+            }
+            PrimitiveMethodKind::Length => {
+                let text = string_argument("length", *self.receiver.clone())?;
+                Ok(RuntimeValue::Number(text.chars().count() as f64))
+            }
+            PrimitiveMethodKind::ToUpper => ToUpper.call(interpreter, receiver),
+            PrimitiveMethodKind::ToLower => ToLower.call(interpreter, receiver),
+            PrimitiveMethodKind::Trim => Trim.call(interpreter, receiver),
+        };
+    }
+
+    fn to_string(&self) -> LoxStr {
+        let name = match self.kind {
+            PrimitiveMethodKind::ToString => "toString",
+            PrimitiveMethodKind::Length => "length",
+            PrimitiveMethodKind::ToUpper => "toUpper",
+            PrimitiveMethodKind::ToLower => "toLower",
+            PrimitiveMethodKind::Trim => "trim",
+        };
+
+        return format!("<fn {name}>").into();
+    }
+}
@@ -1,53 +1,158 @@
-use std::{
-    cell::{Ref, RefCell},
-    collections::HashMap,
-    rc::Rc,
-};
+use std::{cell::RefCell, rc::Rc};
 
 use crate::{
+    ast::stmt::VariableStmt,
+    cycle_collector,
+    environment::Environment,
     interpreter::Interpreter,
     lox_callable::LoxCall,
     lox_function::LoxFunction,
     lox_instance::LoxInstance,
+    ordered_map::OrderedMap,
     runtime_value::{RuntimeResult, RuntimeValue},
     string::LoxStr,
 };
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct LoxClass {
     pub name: LoxStr,
-    pub methods: Rc<RefCell<HashMap<LoxStr, LoxFunction>>>,
+    pub superclass: Option<Rc<LoxClass>>,
+    // In declaration order
+    pub methods: Rc<RefCell<OrderedMap<LoxStr, LoxFunction>>>,
+    pub static_methods: Rc<OrderedMap<LoxStr, LoxFunction>>,
+    // Declared with `var` in the class body, and set on each instance before `init` runs
+    pub fields: Rc<Vec<VariableStmt>>,
+    closure: Rc<RefCell<Environment>>,
 }
 
 impl LoxClass {
-    pub fn new(name: LoxStr, methods: Rc<RefCell<HashMap<LoxStr, LoxFunction>>>) -> Self {
-        return Self { name, methods };
+    pub fn new(
+        name: LoxStr,
+        superclass: Option<Rc<LoxClass>>,
+        methods: Rc<RefCell<OrderedMap<LoxStr, LoxFunction>>>,
+        static_methods: Rc<OrderedMap<LoxStr, LoxFunction>>,
+        fields: Rc<Vec<VariableStmt>>,
+        closure: Rc<RefCell<Environment>>,
+    ) -> Self {
+        cycle_collector::track(&closure);
+
+        return Self {
+            name,
+            superclass,
+            methods,
+            static_methods,
+            fields,
+            closure,
+        };
+    }
+
+    pub fn closure(&self) -> &Rc<RefCell<Environment>> {
+        return &self.closure;
+    }
+
+    pub fn required_arity(&self) -> usize {
+        match self.find_method(&"init".into()) {
+            Some(initializer) => return initializer.required_arity(),
+            None => return 0,
+        }
+    }
+
+    // Looks through the superclasses too, so methods are inherited
+    pub fn find_method(&self, name: &LoxStr) -> Option<LoxFunction> {
+        if let Some(method) = self.methods.borrow().get(name) {
+            return Some(method.clone());
+        }
+
+        if let Some(superclass) = &self.superclass {
+            return superclass.find_method(name);
+        }
+
+        return None;
+    }
+
+    // Inherited like other methods, so `Sub.create()` finds `Base`'s `create`
+    pub fn find_static_method(&self, name: &LoxStr) -> Option<LoxFunction> {
+        if let Some(method) = self.static_methods.get(name) {
+            return Some(method.clone());
+        }
+
+        if let Some(superclass) = &self.superclass {
+            return superclass.find_static_method(name);
+        }
+
+        return None;
+    }
+
+    // Superclass fields are set first, so a subclass's defaults can override them
+    fn init_fields(
+        &self,
+        interpreter: &mut Interpreter,
+        instance: &mut LoxInstance,
+    ) -> RuntimeResult<()> {
+        if let Some(superclass) = &self.superclass {
+            superclass.init_fields(interpreter, instance)?;
+        }
+
+        if self.fields.is_empty() {
+            return Ok(());
+        }
+
+        // Defaults see `this` the same way methods do
+        let mut environment = Environment::enclosed(Rc::clone(&self.closure));
+        environment.define("this".into(), RuntimeValue::LoxInstance(instance.clone()));
+        let environment = Rc::new(RefCell::new(environment));
+
+        for field in self.fields.iter() {
+            let value = match &field.initializer {
+                Some(initializer) => {
+                    match interpreter.evaluate_in(initializer, Rc::clone(&environment)) {
+                        Ok(value) => value,
+                        Err(e) => return Err(e.into_error()),
+                    }
+                }
+                None => RuntimeValue::Nil,
+            };
+
+            instance.set(field.name.clone(), value)?;
+        }
+
+        return Ok(());
     }
+}
 
-    pub fn find_method<'a>(
-        methods: &'a Ref<HashMap<LoxStr, LoxFunction>>,
-        name: &LoxStr,
-    ) -> Option<&'a LoxFunction> {
-        return methods.get(name);
+// Classes are only equal to themselves. Comparing structurally would recurse forever,
+// as the closure holds the class
+impl PartialEq for LoxClass {
+    fn eq(&self, other: &Self) -> bool {
+        return Rc::ptr_eq(&self.methods, &other.methods);
     }
 }
 
 impl LoxCall for LoxClass {
     fn arity(&self) -> usize {
-        match Self::find_method(&self.methods.borrow(), &"init".into()) {
+        match self.find_method(&"init".into()) {
             Some(initializer) => return initializer.arity(),
             None => return 0,
         }
     }
 
+    fn accepts(&self, argc: usize) -> bool {
+        match self.find_method(&"init".into()) {
+            Some(initializer) => return initializer.accepts(argc),
+            None => return argc == 0,
+        }
+    }
+
     fn call(
         &mut self,
         interpreter: &mut Interpreter,
         arguments: Vec<RuntimeValue>,
     ) -> RuntimeResult {
-        let instance = LoxInstance::new(self.clone());
+        let mut instance = LoxInstance::new(self.clone());
+
+        self.init_fields(interpreter, &mut instance)?;
 
-        if let Some(initializer) = Self::find_method(&self.methods.borrow(), &"init".into()) {
+        if let Some(initializer) = self.find_method(&"init".into()) {
             initializer
                 .bind(instance.clone())
                 .call(interpreter, arguments)?;
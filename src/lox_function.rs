@@ -2,29 +2,32 @@ use std::{cell::RefCell, rc::Rc};
 
 use crate::{
     ast::stmt::FunctionStmt,
+    cycle_collector,
     environment::Environment,
     interpreter::Interpreter,
     lox_callable::LoxCall,
     lox_instance::LoxInstance,
-    runtime_value::{RuntimeError, RuntimeResult, RuntimeValue},
+    runtime_value::{ControlFlow, RuntimeResult, RuntimeValue},
     string::LoxStr,
     token::Token,
     token_type::TokenType,
 };
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct LoxFunction {
-    pub declaration: FunctionStmt,
+    pub declaration: Rc<FunctionStmt>,
     pub closure: Rc<RefCell<Environment>>,
     is_initializer: bool,
 }
 
 impl LoxFunction {
     pub fn new(
-        declaration: FunctionStmt,
+        declaration: Rc<FunctionStmt>,
         closure: Rc<RefCell<Environment>>,
         is_initializer: bool,
     ) -> Self {
+        cycle_collector::track(&closure);
+
         return Self {
             declaration,
             closure,
@@ -36,38 +39,77 @@ impl LoxFunction {
         let mut environment = Environment::enclosed(Rc::clone(&self.closure));
         environment.define("this".into(), RuntimeValue::LoxInstance(instance));
         return Self::new(
-            self.declaration.clone(),
+            Rc::clone(&self.declaration),
             Rc::new(RefCell::new(environment)),
             self.is_initializer,
         );
     }
 }
 
+// A function is only equal to itself: each declaration it's created from, and each
+// method binding, makes a new function. This also avoids comparing whole closures
+impl PartialEq for LoxFunction {
+    fn eq(&self, other: &Self) -> bool {
+        return Rc::ptr_eq(&self.declaration, &other.declaration)
+            && Rc::ptr_eq(&self.closure, &other.closure);
+    }
+}
+
+impl LoxFunction {
+    // The parameters without a default
+    pub fn required_arity(&self) -> usize {
+        return self
+            .declaration
+            .defaults
+            .iter()
+            .take_while(|default| default.is_none())
+            .count();
+    }
+}
+
 impl LoxCall for LoxFunction {
     fn arity(&self) -> usize {
         return self.declaration.params.len();
     }
 
+    fn accepts(&self, argc: usize) -> bool {
+        return (self.required_arity()..=self.arity()).contains(&argc);
+    }
+
     fn call(
         &mut self,
         interpreter: &mut Interpreter,
-        mut arguments: Vec<RuntimeValue>,
+        arguments: Vec<RuntimeValue>,
     ) -> RuntimeResult {
-        let mut environment = Environment::enclosed(Rc::clone(&self.closure));
+        let environment = Rc::new(RefCell::new(Environment::enclosed(Rc::clone(
+            &self.closure,
+        ))));
 
-        for i in 0..self.declaration.params.len() {
-            let param = &self.declaration.params[i].lexeme;
-            let arg = std::mem::replace(&mut arguments[i], RuntimeValue::Nil);
+        let mut arguments = arguments.into_iter();
+
+        for (param, default) in self
+            .declaration
+            .params
+            .iter()
+            .zip(&self.declaration.defaults)
+        {
+            let arg = match (arguments.next(), default) {
+                (Some(arg), _) => arg,
+                (None, Some(default)) => {
+                    match interpreter.evaluate_in(default, Rc::clone(&environment)) {
+                        Ok(value) => value,
+                        Err(e) => return Err(e.into_error()),
+                    }
+                }
+                (None, None) => RuntimeValue::Nil,
+            };
 
-            environment.define(param.clone(), arg);
+            environment.borrow_mut().define(param.lexeme.clone(), arg);
         }
 
-        if let Err(e) = interpreter.execute_block(
-            &mut self.declaration.body,
-            Rc::new(RefCell::new(environment)),
-        ) {
+        if let Err(e) = interpreter.execute_block(&self.declaration.body, environment) {
             match e {
-                RuntimeError::NonErrorReturnShortCircuit { value } => {
+                ControlFlow::Return(value) => {
                     if self.is_initializer {
                         return Environment::get_at(
                             Rc::clone(&self.closure),
@@ -76,13 +118,14 @@ impl LoxCall for LoxFunction {
                                 token_type: TokenType::This,
                                 lexeme: "this".into(),
                                 line: 0,
+                                column: 0,
                             },
                         );
                     }
 
-                    return Ok(value.unwrap_or_else(|| RuntimeValue::Nil));
+                    return Ok(value.unwrap_or(RuntimeValue::Nil));
                 }
-                e => return Err(e),
+                e => return Err(e.into_error()),
             }
         }
 
@@ -94,6 +137,7 @@ impl LoxCall for LoxFunction {
                     token_type: TokenType::This,
                     lexeme: "this".into(),
                     line: 0,
+                    column: 0,
                 },
             );
         }
@@ -102,6 +146,11 @@ impl LoxCall for LoxFunction {
     }
 
     fn to_string(&self) -> LoxStr {
+        // An anonymous function's name is already its `<anonymous@line:col>` label
+        if self.declaration.name.token_type == TokenType::Fun {
+            return self.declaration.name.lexeme.clone();
+        }
+
         return format!("<fn {}>", self.declaration.name.lexeme).into();
     }
 }
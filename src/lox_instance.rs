@@ -1,33 +1,67 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 
 use crate::{
     lox_callable::LoxCallable,
     lox_class::LoxClass,
+    ordered_map::OrderedMap,
     runtime_value::{RuntimeError, RuntimeResult, RuntimeValue},
     string::LoxStr,
     token::Token,
 };
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct LoxInstance {
     pub class: LoxClass,
-    pub fields: Rc<RefCell<HashMap<LoxStr, RuntimeValue>>>,
+    // In the order they were first set
+    pub fields: Rc<RefCell<OrderedMap<LoxStr, RuntimeValue>>>,
+    // Shared by every handle to the instance, so freezing one freezes them all
+    frozen: Rc<Cell<bool>>,
+}
+
+// Like functions and classes, instances are only equal to themselves
+impl PartialEq for LoxInstance {
+    fn eq(&self, other: &Self) -> bool {
+        return Rc::ptr_eq(&self.fields, &other.fields);
+    }
 }
 
 impl LoxInstance {
     pub fn new(class: LoxClass) -> Self {
         return Self {
             class,
-            fields: Rc::new(RefCell::new(HashMap::new())),
+            fields: Rc::new(RefCell::new(OrderedMap::new())),
+            frozen: Rc::new(Cell::new(false)),
         };
     }
 
+    // Shallow: the copy gets its own fields map, but field values are still shared.
+    // The copy starts out unfrozen.
+    pub fn copy(&self) -> Self {
+        return Self {
+            class: self.class.clone(),
+            fields: Rc::new(RefCell::new(self.fields.borrow().clone())),
+            frozen: Rc::new(Cell::new(false)),
+        };
+    }
+
+    // There's no unfreezing, so freezing twice is the same as once
+    pub fn freeze(&self) {
+        self.frozen.set(true);
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        return self.frozen.get();
+    }
+
     pub fn get(&self, name: &Token) -> RuntimeResult {
         if let Some(value) = self.fields.borrow().get(&name.lexeme) {
             return Ok(value.clone());
         }
 
-        if let Some(method) = LoxClass::find_method(&self.class.methods.borrow(), &name.lexeme) {
+        if let Some(method) = self.class.find_method(&name.lexeme) {
             return Ok(RuntimeValue::LoxCallable(LoxCallable::LoxFunction(
                 method.bind(self.clone()),
             )));
@@ -39,7 +73,29 @@ impl LoxInstance {
         });
     }
 
-    pub fn set(&mut self, name: Token, value: RuntimeValue) {
+    pub fn set(&mut self, name: Token, value: RuntimeValue) -> RuntimeResult<()> {
+        if self.is_frozen() {
+            return Err(RuntimeError::FrozenInstance {
+                name: name.lexeme,
+                class: self.class.name.clone(),
+                line: name.line,
+            });
+        }
+
         self.fields.borrow_mut().insert(name.lexeme, value);
+        return Ok(());
+    }
+
+    // Returns whether the field existed.
+    // Afterwards, `get` falls back to a method of the same name, if any.
+    pub fn remove(&mut self, name: &LoxStr) -> RuntimeResult<bool> {
+        if self.is_frozen() {
+            return Err(RuntimeError::FrozenInstanceDelete {
+                name: name.clone(),
+                class: self.class.name.clone(),
+            });
+        }
+
+        return Ok(self.fields.borrow_mut().remove(name).is_some());
     }
 }
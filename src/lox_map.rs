@@ -0,0 +1,105 @@
+use crate::{
+    ordered_map::OrderedMap,
+    runtime_value::{RuntimeError, RuntimeResult, RuntimeValue},
+    string::LoxStr,
+};
+
+// The values that can be map keys. Functions, instances, lists and maps are mutable or
+// compared by identity, so they can't be, and neither can NaN, as it isn't equal to itself
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MapKey {
+    Nil,
+    Boolean(bool),
+    // The bits of the number, with `-0` stored as `0` since they compare equal
+    Number(u64),
+    String(LoxStr),
+}
+
+impl MapKey {
+    pub fn new(value: &RuntimeValue) -> RuntimeResult<Self> {
+        return match value {
+            RuntimeValue::Nil => Ok(Self::Nil),
+            RuntimeValue::Boolean(value) => Ok(Self::Boolean(*value)),
+            RuntimeValue::Number(value) if value.is_nan() => Err(RuntimeError::InvalidMapKey {
+                found: "NaN".into(),
+            }),
+            RuntimeValue::Number(value) => Ok(Self::Number((value + 0.0).to_bits())),
+            RuntimeValue::String(value) => Ok(Self::String(value.clone())),
+            value => Err(RuntimeError::InvalidMapKey {
+                found: value.described_type().into(),
+            }),
+        };
+    }
+
+    pub fn value(&self) -> RuntimeValue {
+        return match self {
+            Self::Nil => RuntimeValue::Nil,
+            Self::Boolean(value) => RuntimeValue::Boolean(*value),
+            Self::Number(bits) => RuntimeValue::Number(f64::from_bits(*bits)),
+            Self::String(value) => RuntimeValue::String(value.clone()),
+        };
+    }
+}
+
+// Entries are kept in insertion order, so iterating and printing a map is deterministic.
+// Overwriting a key keeps its original position.
+#[derive(Debug, Clone, Default)]
+pub struct LoxMap {
+    entries: OrderedMap<MapKey, RuntimeValue>,
+}
+
+impl LoxMap {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn get(&self, key: &RuntimeValue) -> RuntimeResult<Option<RuntimeValue>> {
+        return Ok(self.entries.get(&MapKey::new(key)?).cloned());
+    }
+
+    pub fn insert(&mut self, key: RuntimeValue, value: RuntimeValue) -> RuntimeResult<()> {
+        self.entries.insert(MapKey::new(&key)?, value);
+        return Ok(());
+    }
+
+    pub fn entries(&self) -> Vec<(RuntimeValue, RuntimeValue)> {
+        return self
+            .entries
+            .iter()
+            .map(|(key, value)| (key.value(), value.clone()))
+            .collect();
+    }
+
+    pub fn keys(&self) -> Vec<RuntimeValue> {
+        return self.entries.keys().map(|key| key.value()).collect();
+    }
+
+    pub fn values(&self) -> Vec<RuntimeValue> {
+        return self
+            .entries
+            .iter()
+            .map(|(_, value)| value.clone())
+            .collect();
+    }
+}
+
+impl LoxMap {
+    // Equal when they have the same keys, whatever order they were added in, and
+    // `values_equal` holds for each key's values
+    pub fn equals(
+        &self,
+        other: &Self,
+        mut values_equal: impl FnMut(&RuntimeValue, &RuntimeValue) -> bool,
+    ) -> bool {
+        if self.entries.len() != other.entries.len() {
+            return false;
+        }
+
+        return self.entries.iter().all(|(key, value)| {
+            return other
+                .entries
+                .get(key)
+                .is_some_and(|other| values_equal(value, other));
+        });
+    }
+}
@@ -1,4 +1,14 @@
+// The codebase favours explicit `return`s and unit return types, mirroring the book's Java
+#![allow(
+    clippy::needless_return,
+    clippy::unused_unit,
+    clippy::result_large_err,
+    clippy::upper_case_acronyms
+)]
+
 mod ast;
+mod capabilities;
+mod cycle_collector;
 mod environment;
 mod interpreter;
 mod lox;
@@ -6,7 +16,11 @@ mod lox_callable;
 mod lox_class;
 mod lox_function;
 mod lox_instance;
+mod lox_map;
+mod optimizer;
+mod ordered_map;
 mod parser;
+mod random;
 mod resolver;
 mod runtime_value;
 mod scanner;
@@ -14,11 +28,19 @@ mod string;
 mod token;
 mod token_type;
 
-use std::{env, io};
+use std::{env, io, panic, thread};
+
+// Each Lox call nests a good few Rust calls, so the main thread's usual 8 MiB can run out
+// before a --max-call-depth of a few hundred is reached, especially in a debug build
+const STACK_SIZE: usize = 64 * 1024 * 1024;
 
 fn main() -> io::Result<()> {
     let mut args = env::args().collect::<Vec<String>>();
     args.remove(0);
 
-    return lox::run_lox(args);
+    let lox = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || lox::run_lox(args))?;
+
+    return lox.join().unwrap_or_else(|e| panic::resume_unwind(e));
 }
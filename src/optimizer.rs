@@ -0,0 +1,484 @@
+use std::rc::Rc;
+
+use crate::{
+    ast::{expr::*, stmt::*},
+    runtime_value::RuntimeValue,
+    token::Token,
+    token_type::TokenType,
+};
+
+// Folds the parts of a resolved program that always evaluate to the same value, eg.
+// `2 + 3 * 4` becomes `14` and `false or x` becomes `x`, so they aren't worked out again
+// every time they run. Only literals are folded, so nothing that could have a side effect,
+// like a call or a variable, is ever dropped or reordered. Expressions keep their ids, so
+// what the resolver found out about the variables left in them still applies.
+//
+// Anything that would give a different result or lose an error isn't touched, eg.
+// `1 / 0` stays for the interpreter, and `1 or x` stays since strict conditions reject it.
+pub struct Optimizer {
+    // `and` / `or` give a boolean rather than an operand, so `true and x` isn't just `x`
+    boolean_logical: bool,
+}
+
+impl Optimizer {
+    pub fn new(boolean_logical: bool) -> Self {
+        return Self { boolean_logical };
+    }
+
+    pub fn optimize(&mut self, statements: &[Stmt]) -> Vec<Stmt> {
+        return self.stmts(statements);
+    }
+
+    fn stmts(&mut self, statements: &[Stmt]) -> Vec<Stmt> {
+        return statements
+            .iter()
+            .map(|statement| statement.accept(self))
+            .collect();
+    }
+
+    fn stmt(&mut self, statement: &Stmt) -> Box<Stmt> {
+        return Box::new(statement.accept(self));
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Expr {
+        return expr.accept(self);
+    }
+
+    fn boxed(&mut self, expr: &Expr) -> Box<Expr> {
+        return Box::new(self.expr(expr));
+    }
+
+    fn function(&mut self, function: &FunctionStmt) -> FunctionStmt {
+        return FunctionStmt {
+            id: function.id,
+            name: function.name.clone(),
+            params: function.params.clone(),
+            defaults: function
+                .defaults
+                .iter()
+                .map(|default| default.as_ref().map(|default| self.expr(default)))
+                .collect(),
+            body: self.stmts(&function.body),
+        };
+    }
+
+    fn variable(&mut self, stmt: &VariableStmt) -> VariableStmt {
+        return VariableStmt {
+            name: stmt.name.clone(),
+            initializer: stmt.initializer.as_ref().map(|expr| self.expr(expr)),
+        };
+    }
+}
+
+fn literal_value(expr: &Expr) -> Option<RuntimeValue> {
+    let Expr::Literal(literal) = expr else {
+        return None;
+    };
+
+    return Some(RuntimeValue::from(literal));
+}
+
+// A literal for the folded value, where the expression it replaces started. `None` for
+// values a literal can't hold, so the expression is left as it was
+fn literal(id: ExprId, value: RuntimeValue, at: &Token) -> Option<Expr> {
+    let (literal_type, token_type) = match value {
+        RuntimeValue::Number(number) if number.is_finite() => {
+            (LiteralExprType::Number, TokenType::Number(number))
+        }
+        RuntimeValue::Boolean(true) => (LiteralExprType::True, TokenType::True),
+        RuntimeValue::Boolean(false) => (LiteralExprType::False, TokenType::False),
+        _ => return None,
+    };
+
+    return Some(Expr::Literal(LiteralExpr {
+        id,
+        literal_type,
+        token: Token {
+            lexeme: token_type.to_string().into(),
+            token_type,
+            line: at.line,
+            column: at.column,
+        },
+    }));
+}
+
+fn fold_binary(
+    op: &BinaryExprOp,
+    left: &RuntimeValue,
+    right: &RuntimeValue,
+) -> Option<RuntimeValue> {
+    match op {
+        BinaryExprOp::EqualEqual => return Some(RuntimeValue::Boolean(left == right)),
+        BinaryExprOp::NotEqual => return Some(RuntimeValue::Boolean(left != right)),
+        _ => {}
+    }
+
+    // Strings are left alone, as joining or repeating them is checked against a length limit
+    let (RuntimeValue::Number(left), RuntimeValue::Number(right)) = (left, right) else {
+        return None;
+    };
+
+    return Some(match op {
+        BinaryExprOp::EqualEqual | BinaryExprOp::NotEqual => unreachable!(),
+
+        BinaryExprOp::Greater => RuntimeValue::Boolean(left > right),
+        BinaryExprOp::GreaterEqual => RuntimeValue::Boolean(left >= right),
+        BinaryExprOp::Less => RuntimeValue::Boolean(left < right),
+        BinaryExprOp::LessEqual => RuntimeValue::Boolean(left <= right),
+
+        BinaryExprOp::Plus => RuntimeValue::Number(left + right),
+        BinaryExprOp::Minus => RuntimeValue::Number(left - right),
+        BinaryExprOp::Times => RuntimeValue::Number(left * right),
+        BinaryExprOp::Divide => RuntimeValue::Number(left / right),
+    });
+}
+
+impl ExprVisitor<Expr> for Optimizer {
+    fn visit_literal_expr(&mut self, expr: &LiteralExpr) -> Expr {
+        return Expr::Literal(expr.clone());
+    }
+
+    fn visit_logical_expr(&mut self, expr: &LogicalExpr) -> Expr {
+        let left = self.expr(&expr.left);
+        let right = self.expr(&expr.right);
+
+        if let Expr::Literal(literal) = &left {
+            match (&expr.operator.token_type, &literal.literal_type) {
+                (TokenType::QuestionQuestion, LiteralExprType::Nil) => return right,
+                (TokenType::QuestionQuestion, _) => return left,
+
+                // Only `true` and `false`, as other values are an error under strict conditions
+                (
+                    TokenType::And | TokenType::Or,
+                    literal_type @ (LiteralExprType::True | LiteralExprType::False),
+                ) => {
+                    let truthy = *literal_type == LiteralExprType::True;
+                    let short_circuits = if expr.operator.token_type == TokenType::Or {
+                        truthy
+                    } else {
+                        !truthy
+                    };
+
+                    if short_circuits {
+                        return left;
+                    }
+
+                    if !self.boolean_logical {
+                        return right;
+                    }
+                }
+
+                _ => {}
+            }
+        }
+
+        return Expr::Logical(LogicalExpr {
+            id: expr.id,
+            left: Box::new(left),
+            operator: expr.operator.clone(),
+            right: Box::new(right),
+        });
+    }
+
+    fn visit_unary_expr(&mut self, expr: &UnaryExpr) -> Expr {
+        let right = self.expr(&expr.right);
+
+        let folded = literal_value(&right).and_then(|value| match (&expr.op.0, value) {
+            (UnaryExprOp::Minus, RuntimeValue::Number(number)) => {
+                Some(RuntimeValue::Number(-number))
+            }
+            (UnaryExprOp::Minus, _) => None,
+            (UnaryExprOp::Not, value) => Some(RuntimeValue::Boolean(matches!(
+                value,
+                RuntimeValue::Nil | RuntimeValue::Boolean(false)
+            ))),
+        });
+
+        if let Some(folded) = folded.and_then(|value| literal(expr.id, value, &expr.op.1)) {
+            return folded;
+        }
+
+        return Expr::Unary(UnaryExpr {
+            id: expr.id,
+            op: expr.op.clone(),
+            right: Box::new(right),
+        });
+    }
+
+    fn visit_binary_expr(&mut self, expr: &BinaryExpr) -> Expr {
+        let left = self.expr(&expr.left);
+        let right = self.expr(&expr.right);
+
+        if let (Expr::Literal(start), Some(left_value), Some(right_value)) =
+            (&left, literal_value(&left), literal_value(&right))
+        {
+            let folded = fold_binary(&expr.op.0, &left_value, &right_value)
+                .and_then(|value| literal(expr.id, value, &start.token));
+
+            if let Some(folded) = folded {
+                return folded;
+            }
+        }
+
+        return Expr::Binary(BinaryExpr {
+            id: expr.id,
+            left: Box::new(left),
+            op: expr.op.clone(),
+            right: Box::new(right),
+        });
+    }
+
+    fn visit_call_expr(&mut self, expr: &CallExpr) -> Expr {
+        return Expr::Call(CallExpr {
+            id: expr.id,
+            callee: self.boxed(&expr.callee),
+            paren: expr.paren.clone(),
+            arguments: expr
+                .arguments
+                .iter()
+                .map(|argument| self.expr(argument))
+                .collect(),
+        });
+    }
+
+    fn visit_grouping_expr(&mut self, expr: &GroupingExpr) -> Expr {
+        let inner = self.expr(&expr.expr);
+
+        // The parentheses only mattered while parsing
+        if let Expr::Literal(_) = inner {
+            return inner;
+        }
+
+        return Expr::Grouping(GroupingExpr {
+            id: expr.id,
+            left: expr.left.clone(),
+            expr: Box::new(inner),
+            right: expr.right.clone(),
+        });
+    }
+
+    fn visit_variable_expr(&mut self, expr: &VariableExpr) -> Expr {
+        return Expr::Variable(expr.clone());
+    }
+
+    fn visit_assignment_expr(&mut self, expr: &AssignmentExpr) -> Expr {
+        return Expr::Assignment(AssignmentExpr {
+            id: expr.id,
+            name: expr.name.clone(),
+            value: self.boxed(&expr.value),
+        });
+    }
+
+    fn visit_get_expr(&mut self, expr: &GetExpr) -> Expr {
+        return Expr::Get(GetExpr {
+            id: expr.id,
+            object: self.boxed(&expr.object),
+            name: expr.name.clone(),
+            optional: expr.optional,
+        });
+    }
+
+    fn visit_set_expr(&mut self, expr: &SetExpr) -> Expr {
+        return Expr::Set(SetExpr {
+            id: expr.id,
+            object: self.boxed(&expr.object),
+            name: expr.name.clone(),
+            value: self.boxed(&expr.value),
+        });
+    }
+
+    fn visit_this_expr(&mut self, expr: &ThisExpr) -> Expr {
+        return Expr::This(expr.clone());
+    }
+
+    fn visit_super_expr(&mut self, expr: &SuperExpr) -> Expr {
+        return Expr::Super(expr.clone());
+    }
+
+    fn visit_list_expr(&mut self, expr: &ListExpr) -> Expr {
+        return Expr::List(ListExpr {
+            id: expr.id,
+            bracket: expr.bracket.clone(),
+            elements: expr
+                .elements
+                .iter()
+                .map(|element| self.expr(element))
+                .collect(),
+        });
+    }
+
+    fn visit_update_expr(&mut self, expr: &UpdateExpr) -> Expr {
+        return Expr::Update(UpdateExpr {
+            id: expr.id,
+            operator: expr.operator.clone(),
+            target: self.boxed(&expr.target),
+            prefix: expr.prefix,
+        });
+    }
+
+    fn visit_loop_expr(&mut self, expr: &LoopExpr) -> Expr {
+        return Expr::Loop(LoopExpr {
+            id: expr.id,
+            keyword: expr.keyword.clone(),
+            loop_stmt: self.stmt(&expr.loop_stmt),
+        });
+    }
+
+    fn visit_function_expr(&mut self, expr: &FunctionExpr) -> Expr {
+        return Expr::Function(FunctionExpr {
+            id: expr.id,
+            declaration: Rc::new(self.function(&expr.declaration)),
+        });
+    }
+}
+
+impl StmtVisitor<Stmt> for Optimizer {
+    fn visit_block_stmt(&mut self, stmt: &BlockStmt) -> Stmt {
+        return Stmt::Block(BlockStmt {
+            line: stmt.line,
+            stmts: self.stmts(&stmt.stmts),
+        });
+    }
+
+    fn visit_expression_stmt(&mut self, stmt: &ExpressionStmt) -> Stmt {
+        return Stmt::Expression(ExpressionStmt {
+            line: stmt.line,
+            expr: self.expr(&stmt.expr),
+        });
+    }
+
+    fn visit_print_stmt(&mut self, stmt: &PrintStmt) -> Stmt {
+        return Stmt::Print(PrintStmt {
+            line: stmt.line,
+            expr: self.expr(&stmt.expr),
+        });
+    }
+
+    fn visit_variable_stmt(&mut self, stmt: &VariableStmt) -> Stmt {
+        return Stmt::Variable(self.variable(stmt));
+    }
+
+    fn visit_if_stmt(&mut self, stmt: &IfStmt) -> Stmt {
+        return Stmt::If(IfStmt {
+            keyword: stmt.keyword.clone(),
+            condition: self.expr(&stmt.condition),
+            then_branch: self.stmt(&stmt.then_branch),
+            else_branch: stmt.else_branch.as_ref().map(|branch| self.stmt(branch)),
+        });
+    }
+
+    fn visit_while_stmt(&mut self, stmt: &WhileStmt) -> Stmt {
+        return Stmt::While(WhileStmt {
+            keyword: stmt.keyword.clone(),
+            condition: self.expr(&stmt.condition),
+            body: self.stmt(&stmt.body),
+            increment: stmt
+                .increment
+                .as_ref()
+                .map(|increment| self.stmt(increment)),
+            else_branch: stmt.else_branch.as_ref().map(|branch| self.stmt(branch)),
+        });
+    }
+
+    fn visit_loop_stmt(&mut self, stmt: &LoopStmt) -> Stmt {
+        return Stmt::Loop(LoopStmt {
+            keyword: stmt.keyword.clone(),
+            body: self.stmt(&stmt.body),
+        });
+    }
+
+    fn visit_repeat_stmt(&mut self, stmt: &RepeatStmt) -> Stmt {
+        return Stmt::Repeat(RepeatStmt {
+            keyword: stmt.keyword.clone(),
+            count: self.expr(&stmt.count),
+            body: self.stmt(&stmt.body),
+        });
+    }
+
+    fn visit_for_in_stmt(&mut self, stmt: &ForInStmt) -> Stmt {
+        return Stmt::ForIn(ForInStmt {
+            keyword: stmt.keyword.clone(),
+            name: stmt.name.clone(),
+            iterable: self.expr(&stmt.iterable),
+            body: self.stmt(&stmt.body),
+            else_branch: stmt.else_branch.as_ref().map(|branch| self.stmt(branch)),
+        });
+    }
+
+    fn visit_function_stmt(&mut self, stmt: &FunctionStmt) -> Stmt {
+        return Stmt::Function(self.function(stmt));
+    }
+
+    fn visit_return_stmt(&mut self, stmt: &ReturnStmt) -> Stmt {
+        return Stmt::Return(ReturnStmt {
+            keyword: stmt.keyword.clone(),
+            value: stmt.value.as_ref().map(|value| self.expr(value)),
+        });
+    }
+
+    fn visit_class_stmt(&mut self, stmt: &ClassStmt) -> Stmt {
+        return Stmt::Class(ClassStmt {
+            name: stmt.name.clone(),
+            superclass: stmt.superclass.clone(),
+            fields: stmt
+                .fields
+                .iter()
+                .map(|field| self.variable(field))
+                .collect(),
+            methods: stmt
+                .methods
+                .iter()
+                .map(|method| self.function(method))
+                .collect(),
+            static_methods: stmt
+                .static_methods
+                .iter()
+                .map(|method| self.function(method))
+                .collect(),
+        });
+    }
+
+    fn visit_break_stmt(&mut self, stmt: &BreakStmt) -> Stmt {
+        return Stmt::Break(BreakStmt {
+            keyword: stmt.keyword.clone(),
+            value: stmt.value.as_ref().map(|value| self.expr(value)),
+        });
+    }
+
+    fn visit_continue_stmt(&mut self, stmt: &ContinueStmt) -> Stmt {
+        return Stmt::Continue(stmt.clone());
+    }
+
+    fn visit_import_stmt(&mut self, stmt: &ImportStmt) -> Stmt {
+        return Stmt::Import(stmt.clone());
+    }
+
+    fn visit_try_stmt(&mut self, stmt: &TryStmt) -> Stmt {
+        return Stmt::Try(TryStmt {
+            keyword: stmt.keyword.clone(),
+            body: self.stmts(&stmt.body),
+            catch_name: stmt.catch_name.clone(),
+            catch_body: self.stmts(&stmt.catch_body),
+        });
+    }
+
+    fn visit_throw_stmt(&mut self, stmt: &ThrowStmt) -> Stmt {
+        return Stmt::Throw(ThrowStmt {
+            keyword: stmt.keyword.clone(),
+            value: self.expr(&stmt.value),
+        });
+    }
+
+    fn visit_with_stmt(&mut self, stmt: &WithStmt) -> Stmt {
+        return Stmt::With(WithStmt {
+            keyword: stmt.keyword.clone(),
+            value: self.expr(&stmt.value),
+            name: stmt.name.clone(),
+            body: self.stmts(&stmt.body),
+        });
+    }
+
+    fn visit_debugger_stmt(&mut self, stmt: &DebuggerStmt) -> Stmt {
+        return Stmt::Debugger(stmt.clone());
+    }
+}
@@ -0,0 +1,65 @@
+use std::{collections::HashMap, hash::Hash};
+
+// A map that remembers insertion order, so listing its keys gives the same result every
+// run. Overwriting a key keeps its original position.
+#[derive(Debug, Clone)]
+pub struct OrderedMap<K, V> {
+    entries: Vec<(K, V)>,
+    // Each key's position in `entries`
+    index: HashMap<K, usize>,
+}
+
+impl<K, V> Default for OrderedMap<K, V> {
+    fn default() -> Self {
+        return Self {
+            entries: Vec::new(),
+            index: HashMap::new(),
+        };
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> OrderedMap<K, V> {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        return self.index.get(key).map(|&i| &self.entries[i].1);
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        if let Some(&i) = self.index.get(&key) {
+            self.entries[i].1 = value;
+            return;
+        }
+
+        self.index.insert(key.clone(), self.entries.len());
+        self.entries.push((key, value));
+    }
+
+    // The entries after it shift down, so the rest keep their order
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let i = self.index.remove(key)?;
+        let (_, value) = self.entries.remove(i);
+
+        for (key, _) in &self.entries[i..] {
+            if let Some(position) = self.index.get_mut(key) {
+                *position -= 1;
+            }
+        }
+
+        return Some(value);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.entries.len();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(K, V)> {
+        return self.entries.iter();
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        return self.entries.iter().map(|(key, _)| key);
+    }
+}
@@ -6,8 +6,11 @@ use crate::{
     token_type::TokenType,
 };
 
+use std::rc::Rc;
+
 pub type Result<T = ()> = std::result::Result<T, ParserError>;
 
+#[allow(dead_code)]
 pub struct ParserError {
     pub message: String,
 }
@@ -15,11 +18,46 @@ pub struct ParserError {
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+
+    // Set while parsing a statement directly inside a function body,
+    // where a final expression without a ';' becomes the return value
+    allow_trailing_expr: bool,
+
+    // Errors are only kept rather than reported, eg. to see whether a fix-up will help
+    quiet: bool,
+    first_error: Option<(Token, String)>,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        return Self { tokens, current: 0 };
+        // Comments are only kept for tools that want them, they aren't part of the grammar
+        let tokens = tokens
+            .into_iter()
+            .filter(|token| {
+                return !matches!(
+                    token.token_type,
+                    TokenType::LineComment(_) | TokenType::BlockComment(_)
+                );
+            })
+            .collect();
+
+        return Self {
+            tokens,
+            current: 0,
+            allow_trailing_expr: false,
+            quiet: false,
+            first_error: None,
+        };
+    }
+
+    pub fn quiet(mut self) -> Self {
+        self.quiet = true;
+        return self;
+    }
+
+    // Where and why parsing first failed, if it did
+    pub fn first_error(&self) -> Option<&(Token, String)> {
+        return self.first_error.as_ref();
     }
 
     pub fn parse(&mut self) -> Vec<Stmt> {
@@ -40,7 +78,9 @@ impl Parser {
                 return this.class_declaration();
             }
 
-            if this.match_any(&[TokenType::Fun]) {
+            // Otherwise it's an anonymous function, in an expression statement
+            if this.check(&TokenType::Fun) && this.check_next(&TokenType::Identifier) {
+                this.advance();
                 return Ok(Stmt::Function(this.function("function".into())?));
             }
 
@@ -62,14 +102,39 @@ impl Parser {
 
     fn class_declaration(&mut self) -> Result<Stmt> {
         let name = self.consume(&TokenType::Identifier, "Expect class name".to_string())?;
+
+        let superclass = if self.match_any(&[TokenType::Less]) {
+            let name =
+                self.consume(&TokenType::Identifier, "Expect superclass name".to_string())?;
+
+            Some(VariableExpr {
+                id: expr_id(),
+                name,
+            })
+        } else {
+            None
+        };
+
         self.consume(
             &TokenType::LeftBrace,
             "Expect '{' before class body".to_string(),
         )?;
 
+        let mut fields = vec![];
         let mut methods = vec![];
+        let mut static_methods = vec![];
         while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
-            methods.push(self.function("method".into())?);
+            if self.match_any(&[TokenType::Var]) {
+                let Stmt::Variable(field) = self.var_declaration()? else {
+                    unreachable!("var_declaration only builds variable statements");
+                };
+
+                fields.push(field);
+            } else if self.match_any(&[TokenType::Class]) {
+                static_methods.push(self.function("static method".into())?);
+            } else {
+                methods.push(self.function("method".into())?);
+            }
         }
 
         self.consume(
@@ -77,7 +142,13 @@ impl Parser {
             "Expect '}' after class body".to_string(),
         )?;
 
-        return Ok(Stmt::Class(ClassStmt { name, methods }));
+        return Ok(Stmt::Class(ClassStmt {
+            name,
+            superclass,
+            fields,
+            methods,
+            static_methods,
+        }));
     }
 
     fn var_declaration(&mut self) -> Result<Stmt> {
@@ -98,6 +169,9 @@ impl Parser {
     }
 
     fn statement(&mut self) -> Result<Stmt> {
+        // Only the outermost statement of a function body may be a trailing expression
+        let allow_trailing_expr = std::mem::replace(&mut self.allow_trailing_expr, false);
+
         if self.match_any(&[TokenType::If]) {
             return self.if_statement();
         }
@@ -110,30 +184,72 @@ impl Parser {
             return self.for_statement();
         }
 
+        if self.match_any(&[TokenType::Loop]) {
+            return self.loop_statement();
+        }
+
+        if self.match_any(&[TokenType::Repeat]) {
+            return self.repeat_statement();
+        }
+
         if self.match_any(&[TokenType::Print]) {
             return self.print_statement();
         }
 
+        if self.match_any(&[TokenType::Import]) {
+            return self.import_statement();
+        }
+
         if self.match_any(&[TokenType::Return]) {
             return self.return_statement();
         }
 
+        if self.match_any(&[TokenType::Break]) {
+            return self.break_statement();
+        }
+
+        if self.match_any(&[TokenType::Continue]) {
+            return self.continue_statement();
+        }
+
+        if self.match_any(&[TokenType::Try]) {
+            return self.try_statement();
+        }
+
+        if self.match_any(&[TokenType::Throw]) {
+            return self.throw_statement();
+        }
+
+        if self.match_any(&[TokenType::With]) {
+            return self.with_statement();
+        }
+
+        if self.match_any(&[TokenType::Debugger]) {
+            return self.debugger_statement();
+        }
+
         if self.match_any(&[TokenType::LeftBrace]) {
+            let line = self.previous().unwrap().line;
+
             return Ok(Stmt::Block(BlockStmt {
+                line,
                 stmts: self.block()?,
             }));
         }
 
+        if allow_trailing_expr {
+            return self.trailing_expression_statement();
+        }
+
         return self.expression_statement();
     }
 
     fn if_statement(&mut self) -> Result<Stmt> {
-        self.consume(&TokenType::LeftParen, "Expect '(' after 'if'".to_string())?;
+        let keyword = self.previous().cloned();
+
+        let parenthesized = self.open_paren("Expect '(' after 'if'".to_string());
         let condition = self.expression()?;
-        self.consume(
-            &TokenType::RightParen,
-            "Expect ')' after if condition".to_string(),
-        )?;
+        self.close_paren(parenthesized, "Expect ')' after if condition".to_string())?;
 
         let then_branch = self.statement()?;
         let else_branch = if self.match_any(&[TokenType::Else]) {
@@ -143,6 +259,7 @@ impl Parser {
         };
 
         return Ok(Stmt::If(IfStmt {
+            keyword: keyword.unwrap(),
             condition,
             then_branch: Box::new(then_branch),
             else_branch,
@@ -150,26 +267,78 @@ impl Parser {
     }
 
     fn while_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous().cloned();
+
+        let parenthesized = self.open_paren("Expect '(' after 'while'".to_string());
+        let condition = self.expression()?;
+        self.close_paren(
+            parenthesized,
+            "Expect ')' after while condition".to_string(),
+        )?;
+
+        let body = self.statement()?;
+        let else_branch = self.loop_else(&body)?;
+
+        return Ok(Stmt::While(WhileStmt {
+            keyword: keyword.unwrap(),
+            condition,
+            body: Box::new(body),
+            increment: None,
+            else_branch,
+        }));
+    }
+
+    // `while (...) { body } else other`, where `other` runs unless the loop `break`s.
+    // Only after a braced body, so in `if (a) while (b) c; else d;` the `else` still
+    // goes with the `if`, as it would without loop `else`s
+    fn loop_else(&mut self, body: &Stmt) -> Result<Option<Box<Stmt>>> {
+        if matches!(body, Stmt::Block(_)) && self.match_any(&[TokenType::Else]) {
+            return Ok(Some(Box::new(self.statement()?)));
+        }
+
+        return Ok(None);
+    }
+
+    fn loop_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous().cloned();
+        let body = self.statement()?;
+
+        return Ok(Stmt::Loop(LoopStmt {
+            keyword: keyword.unwrap(),
+            body: Box::new(body),
+        }));
+    }
+
+    fn repeat_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous().cloned();
+
         self.consume(
             &TokenType::LeftParen,
-            "Expect '(' after 'while'".to_string(),
+            "Expect '(' after 'repeat'".to_string(),
         )?;
-        let condition = self.expression()?;
+        let count = self.expression()?;
         self.consume(
             &TokenType::RightParen,
-            "Expect ')' after while condition".to_string(),
+            "Expect ')' after repeat count".to_string(),
         )?;
 
         let body = self.statement()?;
 
-        return Ok(Stmt::While(WhileStmt {
-            condition,
+        return Ok(Stmt::Repeat(RepeatStmt {
+            keyword: keyword.unwrap(),
+            count,
             body: Box::new(body),
         }));
     }
 
     fn for_statement(&mut self) -> Result<Stmt> {
-        self.consume(&TokenType::LeftParen, "Expect '(' after 'for'".to_string())?;
+        let keyword = self.previous().cloned().unwrap();
+
+        let parenthesized = self.open_paren("Expect '(' after 'for'".to_string());
+
+        if self.check_for_in() {
+            return self.for_in_statement(keyword, parenthesized);
+        }
 
         let initializer = if self.match_any(&[TokenType::Semicolon]) {
             None
@@ -196,18 +365,17 @@ impl Parser {
             None
         };
 
-        self.consume(
-            &TokenType::RightParen,
-            "Expect ')' after for clauses".to_string(),
-        )?;
+        self.close_paren(parenthesized, "Expect ')' after for clauses".to_string())?;
 
-        let mut body = self.statement()?;
+        let body = self.statement()?;
+        let else_branch = self.loop_else(&body)?;
 
-        if let Some(increment) = increment {
-            body = Stmt::Block(BlockStmt {
-                stmts: vec![body, Stmt::Expression(ExpressionStmt { expr: increment })],
-            });
-        }
+        let increment = increment.map(|increment| {
+            Box::new(Stmt::Expression(ExpressionStmt {
+                line: increment.line(),
+                expr: increment,
+            }))
+        });
 
         let condition = if let Some(condition) = condition {
             condition
@@ -218,18 +386,24 @@ impl Parser {
                 token: Token {
                     lexeme: "true".into(),
                     line: 0,
+                    column: 0,
                     token_type: TokenType::True,
                 },
             })
         };
 
-        body = Stmt::While(WhileStmt {
+        let line = keyword.line;
+        let mut body = Stmt::While(WhileStmt {
+            keyword,
             condition,
             body: Box::new(body),
+            increment,
+            else_branch,
         });
 
         if let Some(initializer) = initializer {
             body = Stmt::Block(BlockStmt {
+                line,
                 stmts: vec![initializer, body],
             });
         }
@@ -237,11 +411,150 @@ impl Parser {
         return Ok(body);
     }
 
+    // Whether the `for` clauses are `name in iterable`, with an optional leading `var`
+    fn check_for_in(&self) -> bool {
+        let offset = if self.check(&TokenType::Var) { 1 } else { 0 };
+
+        let token_type = |offset: usize| {
+            return self
+                .tokens
+                .get(self.current + offset)
+                .map(|token| &token.token_type);
+        };
+
+        return token_type(offset) == Some(&TokenType::Identifier)
+            && token_type(offset + 1) == Some(&TokenType::In);
+    }
+
+    fn for_in_statement(&mut self, keyword: Token, parenthesized: bool) -> Result<Stmt> {
+        self.match_any(&[TokenType::Var]);
+
+        let name = self.consume(&TokenType::Identifier, "Expect variable name".to_string())?;
+        self.consume(
+            &TokenType::In,
+            "Expect 'in' after variable name".to_string(),
+        )?;
+
+        let iterable = self.expression()?;
+
+        self.close_paren(parenthesized, "Expect ')' after for clauses".to_string())?;
+
+        let body = self.statement()?;
+        let else_branch = self.loop_else(&body)?;
+
+        return Ok(Stmt::ForIn(ForInStmt {
+            keyword,
+            name,
+            iterable,
+            body: Box::new(body),
+            else_branch,
+        }));
+    }
+
     fn print_statement(&mut self) -> Result<Stmt> {
+        let line = self.previous().unwrap().line;
         let value = self.expression()?;
         self.consume(&TokenType::Semicolon, "Expect ';' after value.".to_string())?;
 
-        return Ok(Stmt::Print(PrintStmt { expr: value }));
+        return Ok(Stmt::Print(PrintStmt { line, expr: value }));
+    }
+
+    fn import_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous().cloned();
+
+        let path = self.consume(
+            &TokenType::String(Default::default()),
+            "Expect a path string after 'import'".to_string(),
+        )?;
+        self.consume(
+            &TokenType::Semicolon,
+            "Expect ';' after import path".to_string(),
+        )?;
+
+        let TokenType::String(path) = path.token_type else {
+            unreachable!("Consumed a string token");
+        };
+
+        return Ok(Stmt::Import(ImportStmt {
+            keyword: keyword.unwrap(),
+            path,
+        }));
+    }
+
+    fn try_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous().cloned();
+
+        self.consume(&TokenType::LeftBrace, "Expect '{' after 'try'".to_string())?;
+        let body = self.block()?;
+
+        self.consume(
+            &TokenType::Catch,
+            "Expect 'catch' after try block".to_string(),
+        )?;
+        self.consume(
+            &TokenType::LeftParen,
+            "Expect '(' after 'catch'".to_string(),
+        )?;
+        let catch_name = self.consume(
+            &TokenType::Identifier,
+            "Expect error variable name".to_string(),
+        )?;
+        self.consume(
+            &TokenType::RightParen,
+            "Expect ')' after error variable".to_string(),
+        )?;
+
+        self.consume(
+            &TokenType::LeftBrace,
+            "Expect '{' before catch block".to_string(),
+        )?;
+        let catch_body = self.block()?;
+
+        return Ok(Stmt::Try(TryStmt {
+            keyword: keyword.unwrap(),
+            body,
+            catch_name,
+            catch_body,
+        }));
+    }
+
+    fn throw_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous().cloned();
+
+        let value = self.expression()?;
+        self.consume(
+            &TokenType::Semicolon,
+            "Expect ';' after thrown value".to_string(),
+        )?;
+
+        return Ok(Stmt::Throw(ThrowStmt {
+            keyword: keyword.unwrap(),
+            value,
+        }));
+    }
+
+    fn with_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous().cloned();
+
+        let value = self.expression()?;
+        self.consume(&TokenType::As, "Expect 'as' after with value".to_string())?;
+        let name = self.consume(
+            &TokenType::Identifier,
+            "Expect variable name after 'as'".to_string(),
+        )?;
+
+        self.consume(
+            &TokenType::LeftBrace,
+            "Expect '{' before with body".to_string(),
+        )?;
+        let body = self.block()?;
+
+        return Ok(Stmt::With(WithStmt {
+            keyword: keyword.unwrap(),
+            value,
+            name,
+            body,
+        }));
     }
 
     fn return_statement(&mut self) -> Result<Stmt> {
@@ -264,14 +577,86 @@ impl Parser {
         }));
     }
 
+    fn break_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous().cloned();
+
+        let value = if !self.check(&TokenType::Semicolon) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        self.consume(
+            &TokenType::Semicolon,
+            "Expect ';' after 'break'".to_string(),
+        )?;
+
+        return Ok(Stmt::Break(BreakStmt {
+            keyword: keyword.unwrap(),
+            value,
+        }));
+    }
+
+    fn continue_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous().cloned();
+
+        self.consume(
+            &TokenType::Semicolon,
+            "Expect ';' after 'continue'".to_string(),
+        )?;
+
+        return Ok(Stmt::Continue(ContinueStmt {
+            keyword: keyword.unwrap(),
+        }));
+    }
+
+    fn debugger_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous().cloned();
+
+        self.consume(
+            &TokenType::Semicolon,
+            "Expect ';' after 'debugger'".to_string(),
+        )?;
+
+        return Ok(Stmt::Debugger(DebuggerStmt {
+            keyword: keyword.unwrap(),
+        }));
+    }
+
     fn expression_statement(&mut self) -> Result<Stmt> {
+        let line = self.peek().unwrap().line;
+        let expr = self.expression()?;
+        self.consume(
+            &TokenType::Semicolon,
+            "Expect ';' after expression.".to_string(),
+        )?;
+
+        return Ok(Stmt::Expression(ExpressionStmt { line, expr }));
+    }
+
+    fn trailing_expression_statement(&mut self) -> Result<Stmt> {
+        let line = self.peek().unwrap().line;
         let expr = self.expression()?;
+
+        // `fun add(a, b) { a + b }` is sugar for `return a + b;`
+        if self.check(&TokenType::RightBrace) {
+            return Ok(Stmt::Return(ReturnStmt {
+                keyword: Token {
+                    token_type: TokenType::Return,
+                    lexeme: "return".into(),
+                    line: self.peek().unwrap().line,
+                    column: 0,
+                },
+                value: Some(expr),
+            }));
+        }
+
         self.consume(
             &TokenType::Semicolon,
             "Expect ';' after expression.".to_string(),
         )?;
 
-        return Ok(Stmt::Expression(ExpressionStmt { expr }));
+        return Ok(Stmt::Expression(ExpressionStmt { line, expr }));
     }
 
     fn function(&mut self, kind: LoxStr) -> Result<FunctionStmt> {
@@ -282,7 +667,13 @@ impl Parser {
             format!("Expect '(' after {kind} name."),
         )?;
 
+        return self.function_rest(name, kind);
+    }
+
+    // The parameters and body of a function, after its opening '('
+    fn function_rest(&mut self, name: Token, kind: LoxStr) -> Result<FunctionStmt> {
         let mut parameters = vec![];
+        let mut defaults = vec![];
 
         if !self.check(&TokenType::RightParen) {
             loop {
@@ -293,9 +684,24 @@ impl Parser {
                     ));
                 }
 
-                parameters.push(
-                    self.consume(&TokenType::Identifier, "Expect parameter name".to_string())?,
-                );
+                let parameter =
+                    self.consume(&TokenType::Identifier, "Expect parameter name".to_string())?;
+
+                let default = if self.match_any(&[TokenType::Equal]) {
+                    Some(self.expression()?)
+                } else {
+                    None
+                };
+
+                if default.is_none() && defaults.iter().any(Option::is_some) {
+                    return Err(self.error(
+                        "Parameters with defaults must come last".to_string(),
+                        parameter,
+                    ));
+                }
+
+                parameters.push(parameter);
+                defaults.push(default);
 
                 if !self.match_any(&[TokenType::Comma]) {
                     break;
@@ -312,15 +718,37 @@ impl Parser {
             format!("Expect '{{' before {kind} body."),
         )?;
 
-        let body = self.block()?;
+        // Initializers always return `this`, so they can't end in a value
+        let is_initializer = &*kind == "method" && &*name.lexeme == "init";
+        let body = self.function_body(!is_initializer)?;
 
         return Ok(FunctionStmt {
+            id: expr_id(),
             name,
             params: parameters,
+            defaults,
             body,
         });
     }
 
+    fn function_body(&mut self, allow_trailing_expr: bool) -> Result<Vec<Stmt>> {
+        let mut statements = vec![];
+
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            self.allow_trailing_expr = allow_trailing_expr;
+            let statement = self.declaration();
+            self.allow_trailing_expr = false;
+
+            if let Some(statement) = statement {
+                statements.push(statement);
+            }
+        }
+
+        self.consume(&TokenType::RightBrace, "Expect '}' after block".to_string())?;
+
+        return Ok(statements);
+    }
+
     fn block(&mut self) -> Result<Vec<Stmt>> {
         let mut statements = vec![];
 
@@ -340,7 +768,7 @@ impl Parser {
     }
 
     fn assignment(&mut self) -> Result<Expr> {
-        let expr = self.or()?;
+        let expr = self.coalesce()?;
 
         if self.match_any(&[TokenType::Equal]) {
             let equals = self.previous().cloned();
@@ -352,7 +780,12 @@ impl Parser {
                     name: expr.name,
                     value: Box::new(value),
                 }));
-            } else if let Expr::Get(expr) = expr {
+            } else if let Expr::Get(
+                expr @ GetExpr {
+                    optional: false, ..
+                },
+            ) = expr
+            {
                 return Ok(Expr::Set(SetExpr {
                     id: expr_id(),
                     object: expr.object,
@@ -361,10 +794,32 @@ impl Parser {
                 }));
             }
 
-            return Err(self.error(
-                format!("[{}:{}] Invalid assignment target", file!(), line!()),
-                equals.unwrap(),
-            ));
+            let message = match expr {
+                Expr::This(_) => "Cannot assign to 'this'".to_string(),
+                Expr::Call(_) => "Cannot assign to the result of a call".to_string(),
+                Expr::Get(_) => "Cannot assign to an optional property access".to_string(),
+                _ => "Invalid assignment target".to_string(),
+            };
+
+            return Err(self.error(message, equals.unwrap()));
+        }
+
+        return Ok(expr);
+    }
+
+    fn coalesce(&mut self) -> Result<Expr> {
+        let mut expr = self.or()?;
+
+        while self.match_any(&[TokenType::QuestionQuestion]) {
+            let operator = self.previous().unwrap().clone();
+            let right = self.or()?;
+
+            expr = Expr::Logical(LogicalExpr {
+                id: expr_id(),
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            });
         }
 
         return Ok(expr);
@@ -373,7 +828,7 @@ impl Parser {
     fn or(&mut self) -> Result<Expr> {
         let mut expr = self.and()?;
 
-        while self.match_any(&[TokenType::Or]) {
+        while self.match_any(&[TokenType::Or, TokenType::Xor]) {
             let operator = self.previous().unwrap().clone();
             let right = self.and()?;
 
@@ -557,7 +1012,52 @@ impl Parser {
             }));
         }
 
-        return self.call();
+        if self.match_any(&[TokenType::PlusPlus, TokenType::MinusMinus]) {
+            let operator = self.previous().unwrap().clone();
+            let target = self.unary()?;
+
+            return self.update(operator, target, true);
+        }
+
+        return self.postfix();
+    }
+
+    fn postfix(&mut self) -> Result<Expr> {
+        let expr = self.call()?;
+
+        if self.match_any(&[TokenType::PlusPlus, TokenType::MinusMinus]) {
+            let operator = self.previous().unwrap().clone();
+
+            return self.update(operator, expr, false);
+        }
+
+        return Ok(expr);
+    }
+
+    fn update(&mut self, operator: Token, target: Expr, prefix: bool) -> Result<Expr> {
+        if !matches!(
+            target,
+            Expr::Variable(_)
+                | Expr::Get(GetExpr {
+                    optional: false,
+                    ..
+                })
+        ) {
+            return Err(self.error(
+                format!(
+                    "Can only apply '{}' to a variable or property",
+                    operator.lexeme
+                ),
+                operator,
+            ));
+        }
+
+        return Ok(Expr::Update(UpdateExpr {
+            id: expr_id(),
+            operator,
+            target: Box::new(target),
+            prefix,
+        }));
     }
 
     fn call(&mut self) -> Result<Expr> {
@@ -566,7 +1066,9 @@ impl Parser {
         loop {
             if self.match_any(&[TokenType::LeftParen]) {
                 expr = self.finish_call(expr)?;
-            } else if self.match_any(&[TokenType::Dot]) {
+            } else if self.match_any(&[TokenType::Dot, TokenType::QuestionDot]) {
+                let optional = self.previous().unwrap().token_type == TokenType::QuestionDot;
+
                 let name = self.consume(
                     &TokenType::Identifier,
                     "Expect property name '.'".to_string(),
@@ -576,6 +1078,7 @@ impl Parser {
                     id: expr_id(),
                     object: Box::new(expr),
                     name,
+                    optional,
                 });
             } else {
                 break;
@@ -667,6 +1170,53 @@ impl Parser {
             }));
         };
 
+        if self.match_any(&[TokenType::While]) {
+            return Ok(Expr::Loop(LoopExpr {
+                id: expr_id(),
+                keyword: token,
+                loop_stmt: Box::new(self.while_statement()?),
+            }));
+        }
+
+        if self.match_any(&[TokenType::Loop]) {
+            return Ok(Expr::Loop(LoopExpr {
+                id: expr_id(),
+                keyword: token,
+                loop_stmt: Box::new(self.loop_statement()?),
+            }));
+        }
+
+        if self.match_any(&[TokenType::Fun]) {
+            self.consume(&TokenType::LeftParen, "Expect '(' after 'fun'".to_string())?;
+
+            // Named after where it's written, keeping the `fun` token type to mark it anonymous
+            let name = Token {
+                token_type: TokenType::Fun,
+                lexeme: format!("<anonymous@{}:{}>", token.line, token.column).into(),
+                line: token.line,
+                column: token.column,
+            };
+
+            return Ok(Expr::Function(FunctionExpr {
+                id: expr_id(),
+                declaration: Rc::new(self.function_rest(name, "function".into())?),
+            }));
+        }
+
+        if self.match_any(&[TokenType::Super]) {
+            self.consume(&TokenType::Dot, "Expect '.' after 'super'".to_string())?;
+            let method = self.consume(
+                &TokenType::Identifier,
+                "Expect superclass method name".to_string(),
+            )?;
+
+            return Ok(Expr::Super(SuperExpr {
+                id: expr_id(),
+                keyword: token,
+                method,
+            }));
+        }
+
         if self.match_any(&[TokenType::This]) {
             return Ok(Expr::This(ThisExpr {
                 id: expr_id(),
@@ -681,6 +1231,31 @@ impl Parser {
             }));
         }
 
+        if self.match_any(&[TokenType::LeftBracket]) {
+            let mut elements = vec![];
+
+            if !self.check(&TokenType::RightBracket) {
+                loop {
+                    elements.push(self.expression()?);
+
+                    if !self.match_any(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+
+            self.consume(
+                &TokenType::RightBracket,
+                "Expect ']' after list elements".to_string(),
+            )?;
+
+            return Ok(Expr::List(ListExpr {
+                id: expr_id(),
+                bracket: token,
+                elements,
+            }));
+        }
+
         if self.match_any(&[TokenType::LeftParen]) {
             let expr = self.expression()?;
 
@@ -729,8 +1304,39 @@ impl Parser {
         return Err(self.error(err_message, self.peek().unwrap().clone()));
     }
 
-    fn error(&self, message: String, token: Token) -> ParserError {
-        lox::token_error(token, &message);
+    // An error production for a condition missing its `(`, eg. `if x > 1 { ... }`.
+    // The error is reported, but parsing carries on as if it were there, rather than
+    // synchronizing part way through the statement and reporting errors that aren't real.
+    // Returns whether the `(` was there.
+    fn open_paren(&mut self, err_message: String) -> bool {
+        if self.match_any(&[TokenType::LeftParen]) {
+            return true;
+        }
+
+        self.error(err_message, self.peek().unwrap().clone());
+        return false;
+    }
+
+    // Only required if the `(` was, since a missing `(` has already been reported
+    fn close_paren(&mut self, parenthesized: bool, err_message: String) -> Result<()> {
+        if parenthesized {
+            self.consume(&TokenType::RightParen, err_message)?;
+        } else {
+            self.match_any(&[TokenType::RightParen]);
+        }
+
+        return Ok(());
+    }
+
+    fn error(&mut self, message: String, token: Token) -> ParserError {
+        if !self.quiet {
+            lox::token_error(token.clone(), &message);
+        }
+
+        if self.first_error.is_none() {
+            self.first_error = Some((token, message.clone()));
+        }
+
         return ParserError { message };
     }
 
@@ -746,10 +1352,19 @@ impl Parser {
 
             if let Some(peek) = self.peek() {
                 match peek.token_type {
-                    TokenType::Class
+                    TokenType::Break
+                    | TokenType::Class
+                    | TokenType::Continue
+                    | TokenType::Debugger
                     | TokenType::For
                     | TokenType::Fun
                     | TokenType::If
+                    | TokenType::Import
+                    | TokenType::Throw
+                    | TokenType::Try
+                    | TokenType::With
+                    | TokenType::Loop
+                    | TokenType::Repeat
                     | TokenType::Print
                     | TokenType::Return
                     | TokenType::Var
@@ -782,6 +1397,13 @@ impl Parser {
             .unwrap_or(false);
     }
 
+    fn check_next(&self, token_type: &TokenType) -> bool {
+        return self
+            .tokens
+            .get(self.current + 1)
+            .is_some_and(|next| next.token_type == *token_type);
+    }
+
     fn advance(&mut self) -> Option<&Token> {
         if !self.is_at_end() {
             self.current += 1;
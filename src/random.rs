@@ -0,0 +1,40 @@
+use std::time::SystemTime;
+
+// A xorshift64* generator, which is plenty for scripts and saves pulling in a crate
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new() -> Self {
+        let epoch_time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+
+        return Self::seeded(epoch_time.as_nanos() as u64);
+    }
+
+    pub fn seeded(seed: u64) -> Self {
+        // Xorshift gets stuck on a zero state
+        return Self {
+            state: seed ^ 0x9E37_79B9_7F4A_7C15,
+        };
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        if self.state == 0 {
+            self.state = 0x9E37_79B9_7F4A_7C15;
+        }
+
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+
+        return self.state.wrapping_mul(0x2545_F491_4F6C_DD1D);
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        return (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+    }
+}
@@ -14,19 +14,36 @@ enum FunctionType {
     Function,
     Initializer,
     Method,
+    StaticMethod,
+    FieldDefault,
+    ParameterDefault,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum ClassType {
     None,
     Class,
+    Subclass,
+}
+
+// A name declared in a local scope
+struct Local {
+    // Where it was declared, to point back to when it's redeclared
+    declaration: Token,
+    // Whether its initializer has been resolved, so it can be read
+    defined: bool,
 }
 
 pub struct Resolver<'a> {
     interpreter: &'a mut Interpreter,
-    scopes: Vec<HashMap<LoxStr, bool>>,
+    scopes: Vec<HashMap<LoxStr, Local>>,
     current_function: FunctionType,
     current_class: ClassType,
+    // Also set inside the functions a static method declares, as they can't see `this` either
+    in_static_method: bool,
+    in_loop: bool,
+    // The functions being resolved, innermost last, with the index of their scope
+    functions: Vec<(ExprId, usize)>,
 }
 
 impl<'a> Resolver<'a> {
@@ -36,6 +53,9 @@ impl<'a> Resolver<'a> {
             scopes: vec![],
             current_function: FunctionType::None,
             current_class: ClassType::None,
+            in_static_method: false,
+            in_loop: false,
+            functions: vec![],
         };
     }
 
@@ -53,24 +73,60 @@ impl<'a> Resolver<'a> {
 
     fn declare(&mut self, name: &Token) {
         if let Some(scope) = self.scopes.last_mut() {
-            if scope.contains_key(&name.lexeme) {
+            if let Some(existing) = scope.get(&name.lexeme) {
                 lox::token_error(
                     name.clone(),
-                    "Already a variable with this name in this scope",
+                    &format!(
+                        "Already a variable '{}' declared on line {}",
+                        name.lexeme, existing.declaration.line
+                    ),
                 );
             }
 
-            scope.insert(name.lexeme.clone(), false);
+            scope.insert(
+                name.lexeme.clone(),
+                Local {
+                    declaration: name.clone(),
+                    defined: false,
+                },
+            );
         }
     }
 
     fn define(&mut self, name: &Token) {
         if let Some(scope) = self.scopes.last_mut() {
-            scope.insert(name.lexeme.clone(), true);
+            scope.insert(
+                name.lexeme.clone(),
+                Local {
+                    declaration: name.clone(),
+                    defined: true,
+                },
+            );
+        }
+    }
+
+    // For `this` and `super`, which the class declaration binds without naming them
+    fn define_implicit(&mut self, name: &str, class_name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(
+                name.into(),
+                Local {
+                    declaration: class_name.clone(),
+                    defined: true,
+                },
+            );
         }
     }
 
     fn resolve_stmts(&mut self, statements: &Vec<Stmt>) {
+        // Functions are hoisted to the top of their block, so siblings can call each other
+        for statement in statements {
+            if let Stmt::Function(function) = statement {
+                self.declare(&function.name);
+                self.define(&function.name);
+            }
+        }
+
         for statement in statements {
             self.resolve_stmt(statement);
         }
@@ -85,7 +141,7 @@ impl<'a> Resolver<'a> {
     }
 
     fn resolve_local(&mut self, expr: &Expr, name: &Token) {
-        if self.scopes.len() == 0 {
+        if self.scopes.is_empty() {
             return;
         }
 
@@ -95,6 +151,14 @@ impl<'a> Resolver<'a> {
             if self.scopes[i].contains_key(&name.lexeme) {
                 self.interpreter
                     .resolve(expr.id(), self.scopes.len() - 1 - i);
+
+                // Declared outside of these functions, so their closures keep it alive
+                for (function, scope) in &self.functions {
+                    if *scope > i {
+                        self.interpreter.capture(*function, &name.lexeme);
+                    }
+                }
+
                 return;
             }
 
@@ -110,25 +174,40 @@ impl<'a> Resolver<'a> {
         let enclosing_function = self.current_function;
         self.current_function = function_type;
 
+        // A function body can't break out of a loop it's declared in
+        let enclosing_loop = std::mem::replace(&mut self.in_loop, false);
+
+        self.functions.push((function.id, self.scopes.len()));
         self.begin_scope();
 
-        for param in &function.params {
+        // Defaults are evaluated in the call's scope, so can use earlier parameters
+        for (param, default) in function.params.iter().zip(&function.defaults) {
             self.declare(param);
+
+            // Like a field default, it's an expression of its own rather than part of the body
+            if let Some(default) = default {
+                self.current_function = FunctionType::ParameterDefault;
+                self.resolve_expr(default);
+                self.current_function = function_type;
+            }
+
             self.define(param);
         }
 
         self.resolve_stmts(&function.body);
 
         self.end_scope();
+        self.functions.pop();
 
         self.current_function = enclosing_function;
+        self.in_loop = enclosing_loop;
     }
 }
 
 impl ExprVisitor<()> for Resolver<'_> {
     fn visit_variable_expr(&mut self, expr: &VariableExpr) -> () {
         if let Some(scope) = self.scopes.last() {
-            if let Some(false) = scope.get(&expr.name.lexeme) {
+            if let Some(Local { defined: false, .. }) = scope.get(&expr.name.lexeme) {
                 lox::token_error(
                     expr.name.clone(),
                     "Can't read local variable in its own initializer",
@@ -174,6 +253,11 @@ impl ExprVisitor<()> for Resolver<'_> {
         self.resolve_expr(&expr.right);
     }
 
+    fn visit_update_expr(&mut self, expr: &UpdateExpr) -> () {
+        // The target is read and written through the same expression, so one lookup serves both
+        self.resolve_expr(&expr.target);
+    }
+
     fn visit_get_expr(&mut self, expr: &GetExpr) -> () {
         self.resolve_expr(&expr.object);
     }
@@ -189,8 +273,51 @@ impl ExprVisitor<()> for Resolver<'_> {
             return;
         }
 
+        if self.in_static_method {
+            lox::token_error(expr.keyword.clone(), "Can't use 'this' in a static method");
+            return;
+        }
+
         self.resolve_local(&Expr::This(expr.clone()), &expr.keyword);
     }
+
+    fn visit_super_expr(&mut self, expr: &SuperExpr) -> () {
+        match self.current_class {
+            ClassType::None => {
+                lox::token_error(expr.keyword.clone(), "Can't use 'super' outside of a class");
+                return;
+            }
+            ClassType::Class => {
+                lox::token_error(
+                    expr.keyword.clone(),
+                    "Can't use 'super' in a class with no superclass",
+                );
+                return;
+            }
+            ClassType::Subclass => {}
+        }
+
+        if self.in_static_method {
+            lox::token_error(expr.keyword.clone(), "Can't use 'super' in a static method");
+            return;
+        }
+
+        self.resolve_local(&Expr::Super(expr.clone()), &expr.keyword);
+    }
+
+    fn visit_list_expr(&mut self, expr: &ListExpr) -> () {
+        for element in &expr.elements {
+            self.resolve_expr(element);
+        }
+    }
+
+    fn visit_loop_expr(&mut self, expr: &LoopExpr) -> () {
+        self.resolve_stmt(&expr.loop_stmt);
+    }
+
+    fn visit_function_expr(&mut self, expr: &FunctionExpr) -> () {
+        self.resolve_function(&expr.declaration, FunctionType::Function);
+    }
 }
 
 impl StmtVisitor<()> for Resolver<'_> {
@@ -213,8 +340,7 @@ impl StmtVisitor<()> for Resolver<'_> {
     }
 
     fn visit_function_stmt(&mut self, stmt: &FunctionStmt) -> () {
-        self.declare(&stmt.name);
-        self.define(&stmt.name);
+        // Already declared by `resolve_stmts`
         self.resolve_function(stmt, FunctionType::Function);
     }
 
@@ -240,8 +366,22 @@ impl StmtVisitor<()> for Resolver<'_> {
             lox::token_error(stmt.keyword.clone(), "Can't return from top-level code");
         }
 
+        if self.current_function == FunctionType::FieldDefault {
+            lox::token_error(stmt.keyword.clone(), "Can't return from a field default");
+        }
+
+        if self.current_function == FunctionType::ParameterDefault {
+            lox::token_error(
+                stmt.keyword.clone(),
+                "Can't return from a parameter default",
+            );
+        }
+
         if let Some(value) = &stmt.value {
-            if self.current_function == FunctionType::Initializer {
+            // `return this;` is allowed though, as it's what an initializer returns anyway
+            let returns_this = matches!(value, Expr::This(_));
+
+            if self.current_function == FunctionType::Initializer && !returns_this {
                 lox::token_error(
                     stmt.keyword.clone(),
                     "Can't return a value from an initializer",
@@ -254,21 +394,154 @@ impl StmtVisitor<()> for Resolver<'_> {
 
     fn visit_while_stmt(&mut self, stmt: &WhileStmt) -> () {
         self.resolve_expr(&stmt.condition);
+
+        let enclosing_loop = std::mem::replace(&mut self.in_loop, true);
         self.resolve_stmt(&stmt.body);
+        self.in_loop = enclosing_loop;
+
+        if let Some(increment) = &stmt.increment {
+            self.resolve_stmt(increment);
+        }
+
+        // Runs after the loop, so a `break` in it leaves an enclosing loop
+        if let Some(else_branch) = &stmt.else_branch {
+            self.resolve_stmt(else_branch);
+        }
+    }
+
+    fn visit_loop_stmt(&mut self, stmt: &LoopStmt) -> () {
+        let enclosing_loop = std::mem::replace(&mut self.in_loop, true);
+        self.resolve_stmt(&stmt.body);
+        self.in_loop = enclosing_loop;
+
+        if !can_exit_loop(&stmt.body, false) {
+            lox::token_warning(stmt.keyword.clone(), "Infinite loop with no break");
+        }
+    }
+
+    fn visit_repeat_stmt(&mut self, stmt: &RepeatStmt) -> () {
+        self.resolve_expr(&stmt.count);
+
+        let enclosing_loop = std::mem::replace(&mut self.in_loop, true);
+        self.resolve_stmt(&stmt.body);
+        self.in_loop = enclosing_loop;
+    }
+
+    fn visit_for_in_stmt(&mut self, stmt: &ForInStmt) -> () {
+        self.resolve_expr(&stmt.iterable);
+
+        self.begin_scope();
+        self.declare(&stmt.name);
+        self.define(&stmt.name);
+
+        let enclosing_loop = std::mem::replace(&mut self.in_loop, true);
+        self.resolve_stmt(&stmt.body);
+        self.in_loop = enclosing_loop;
+
+        self.end_scope();
+
+        if let Some(else_branch) = &stmt.else_branch {
+            self.resolve_stmt(else_branch);
+        }
+    }
+
+    fn visit_break_stmt(&mut self, stmt: &BreakStmt) -> () {
+        if !self.in_loop {
+            lox::token_error(stmt.keyword.clone(), "Can't break outside of a loop");
+        }
+
+        if let Some(value) = &stmt.value {
+            self.resolve_expr(value);
+        }
+    }
+
+    fn visit_continue_stmt(&mut self, stmt: &ContinueStmt) -> () {
+        if !self.in_loop {
+            lox::token_error(stmt.keyword.clone(), "Can't continue outside of a loop");
+        }
+    }
+
+    fn visit_debugger_stmt(&mut self, _: &DebuggerStmt) -> () {}
+
+    fn visit_try_stmt(&mut self, stmt: &TryStmt) -> () {
+        self.begin_scope();
+        self.resolve_stmts(&stmt.body);
+        self.end_scope();
+
+        self.begin_scope();
+        self.declare(&stmt.catch_name);
+        self.define(&stmt.catch_name);
+        self.resolve_stmts(&stmt.catch_body);
+        self.end_scope();
+    }
+
+    fn visit_throw_stmt(&mut self, stmt: &ThrowStmt) -> () {
+        self.resolve_expr(&stmt.value);
+    }
+
+    fn visit_with_stmt(&mut self, stmt: &WithStmt) -> () {
+        self.resolve_expr(&stmt.value);
+
+        self.begin_scope();
+        self.declare(&stmt.name);
+        self.define(&stmt.name);
+        self.resolve_stmts(&stmt.body);
+        self.end_scope();
+    }
+
+    fn visit_import_stmt(&mut self, _: &ImportStmt) -> () {
+        // The imported file is resolved on its own when it runs
     }
 
     fn visit_class_stmt(&mut self, stmt: &ClassStmt) -> () {
         let enclosing_class = self.current_class;
         self.current_class = ClassType::Class;
 
+        // A class inside a static method has its own `this`
+        let enclosing_static = std::mem::replace(&mut self.in_static_method, false);
+
         self.declare(&stmt.name);
         self.define(&stmt.name);
 
+        if let Some(superclass) = &stmt.superclass {
+            if superclass.name.lexeme == stmt.name.lexeme {
+                lox::token_error(superclass.name.clone(), "A class can't inherit from itself");
+            }
+
+            self.current_class = ClassType::Subclass;
+            self.visit_variable_expr(superclass);
+        }
+
+        // Static methods are resolved outside the `super` / `this` scopes, as they close
+        // over the scope the class is declared in
+        self.in_static_method = true;
+        for method in &stmt.static_methods {
+            self.resolve_function(method, FunctionType::StaticMethod);
+        }
+        self.in_static_method = false;
+
+        if stmt.superclass.is_some() {
+            self.begin_scope();
+            self.define_implicit("super", &stmt.name);
+        }
+
         self.begin_scope();
-        if let Some(scope) = self.scopes.last_mut() {
-            scope.insert("this".into(), true);
+        self.define_implicit("this", &stmt.name);
+
+        // Field defaults are evaluated for each new instance, so can use `this`
+        let enclosing_function =
+            std::mem::replace(&mut self.current_function, FunctionType::FieldDefault);
+        let enclosing_loop = std::mem::replace(&mut self.in_loop, false);
+
+        for field in &stmt.fields {
+            if let Some(initializer) = &field.initializer {
+                self.resolve_expr(initializer);
+            }
         }
 
+        self.current_function = enclosing_function;
+        self.in_loop = enclosing_loop;
+
         for method in &stmt.methods {
             let declaration = match method.name.lexeme.as_ref() {
                 "init" => FunctionType::Initializer,
@@ -280,6 +553,52 @@ impl StmtVisitor<()> for Resolver<'_> {
 
         self.end_scope();
 
+        if stmt.superclass.is_some() {
+            self.end_scope();
+        }
+
         self.current_class = enclosing_class;
+        self.in_static_method = enclosing_static;
     }
 }
+
+// Whether `stmt` contains a `break` or `return` that would leave the loop it's the body of.
+// `nested` is set once inside an inner loop, whose breaks only leave that loop.
+fn can_exit_loop(stmt: &Stmt, nested: bool) -> bool {
+    return match stmt {
+        Stmt::Break(_) => !nested,
+        Stmt::Return(_) => true,
+        Stmt::Block(stmt) => stmt.stmts.iter().any(|stmt| can_exit_loop(stmt, nested)),
+        Stmt::If(stmt) => {
+            can_exit_loop(&stmt.then_branch, nested)
+                || stmt
+                    .else_branch
+                    .as_ref()
+                    .is_some_and(|else_branch| can_exit_loop(else_branch, nested))
+        }
+        Stmt::While(stmt) => {
+            can_exit_loop(&stmt.body, true)
+                || stmt
+                    .else_branch
+                    .as_ref()
+                    .is_some_and(|else_branch| can_exit_loop(else_branch, nested))
+        }
+        Stmt::Loop(stmt) => can_exit_loop(&stmt.body, true),
+        Stmt::Repeat(stmt) => can_exit_loop(&stmt.body, true),
+        Stmt::ForIn(stmt) => {
+            can_exit_loop(&stmt.body, true)
+                || stmt
+                    .else_branch
+                    .as_ref()
+                    .is_some_and(|else_branch| can_exit_loop(else_branch, nested))
+        }
+        Stmt::With(stmt) => stmt.body.iter().any(|stmt| can_exit_loop(stmt, nested)),
+        // A `throw` also leaves the loop, but is normally an error path
+        Stmt::Try(stmt) => stmt
+            .body
+            .iter()
+            .chain(&stmt.catch_body)
+            .any(|stmt| can_exit_loop(stmt, nested)),
+        _ => false,
+    };
+}
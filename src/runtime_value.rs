@@ -1,13 +1,15 @@
 use crate::{
-    ast::expr::*, lox_callable::LoxCallable, lox_instance::LoxInstance, string::LoxStr,
-    token::Token, token_type::TokenType,
+    ast::expr::*, lox_callable::LoxCallable, lox_instance::LoxInstance, lox_map::LoxMap,
+    string::LoxStr, token::Token, token_type::TokenType,
 };
 
+use std::{cell::RefCell, rc::Rc};
+
 use thiserror::Error;
 
 pub type RuntimeResult<T = RuntimeValue, E = RuntimeError> = Result<T, E>;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub enum RuntimeValue {
     Nil,
     Boolean(bool),
@@ -15,6 +17,136 @@ pub enum RuntimeValue {
     String(LoxStr),
     LoxCallable(LoxCallable),
     LoxInstance(LoxInstance),
+    List(Rc<RefCell<Vec<RuntimeValue>>>),
+    Map(Rc<RefCell<LoxMap>>),
+    // What `var x;` holds under `strict_init`, until it's assigned. Reading one is an error,
+    // so it's never seen by a script
+    Uninitialized,
+}
+
+impl RuntimeValue {
+    pub const TYPE_NAMES: [&'static str; 9] = [
+        "nil", "boolean", "number", "string", "function", "class", "instance", "list", "map",
+    ];
+
+    pub fn list(values: Vec<RuntimeValue>) -> Self {
+        return Self::List(Rc::new(RefCell::new(values)));
+    }
+
+    pub fn map(map: LoxMap) -> Self {
+        return Self::Map(Rc::new(RefCell::new(map)));
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        return match self {
+            Self::Nil => "nil",
+            Self::Boolean(_) => "boolean",
+            Self::Number(_) => "number",
+            Self::String(_) => "string",
+            Self::LoxCallable(LoxCallable::LoxClass(_)) => "class",
+            Self::LoxCallable(_) => "function",
+            Self::LoxInstance(_) => "instance",
+            Self::List(_) => "list",
+            Self::Map(_) => "map",
+            Self::Uninitialized => "uninitialized",
+        };
+    }
+
+    // The type name with its article, eg. "a number" or "an instance"
+    pub fn described_type(&self) -> String {
+        let type_name = self.type_name();
+
+        if type_name.starts_with(['a', 'e', 'i', 'o', 'u']) {
+            return format!("an {type_name}");
+        }
+
+        return format!("a {type_name}");
+    }
+}
+
+// Lists and maps are equal when their contents are, see `RuntimeValue::equals`
+impl PartialEq for RuntimeValue {
+    fn eq(&self, other: &Self) -> bool {
+        return self.equals(other, &mut vec![]);
+    }
+}
+
+impl RuntimeValue {
+    // `comparing` holds the lists and maps being compared further up. Meeting one of those
+    // pairs again means both contain themselves, and rather than recursing forever they count
+    // as unequal. The same list or map is always equal to itself though, cycles or not.
+    fn equals(&self, other: &Self, comparing: &mut Vec<(*const (), *const ())>) -> bool {
+        return match (self, other) {
+            (Self::Nil, Self::Nil) => true,
+            (Self::Boolean(left), Self::Boolean(right)) => left == right,
+            (Self::Number(left), Self::Number(right)) => left == right,
+            (Self::String(left), Self::String(right)) => left == right,
+            (Self::LoxCallable(left), Self::LoxCallable(right)) => left == right,
+            (Self::LoxInstance(left), Self::LoxInstance(right)) => left == right,
+
+            (Self::List(left), Self::List(right)) => {
+                if Rc::ptr_eq(left, right) {
+                    return true;
+                }
+
+                let pair = (
+                    Rc::as_ptr(left) as *const (),
+                    Rc::as_ptr(right) as *const (),
+                );
+                if comparing.contains(&pair) {
+                    return false;
+                }
+
+                let (left, right) = (left.borrow(), right.borrow());
+                if left.len() != right.len() {
+                    return false;
+                }
+
+                comparing.push(pair);
+                let equal = left
+                    .iter()
+                    .zip(right.iter())
+                    .all(|(left, right)| left.equals(right, comparing));
+                comparing.pop();
+
+                equal
+            }
+
+            (Self::Map(left), Self::Map(right)) => {
+                if Rc::ptr_eq(left, right) {
+                    return true;
+                }
+
+                let pair = (
+                    Rc::as_ptr(left) as *const (),
+                    Rc::as_ptr(right) as *const (),
+                );
+                if comparing.contains(&pair) {
+                    return false;
+                }
+
+                comparing.push(pair);
+                let equal = left
+                    .borrow()
+                    .equals(&right.borrow(), |left, right| left.equals(right, comparing));
+                comparing.pop();
+
+                equal
+            }
+
+            _ => false,
+        };
+    }
+}
+
+// Numbers are all floats, so anything wanting an integer goes through here rather than
+// casting with `as`, which would silently truncate fractions and saturate huge values
+pub fn whole_number(number: f64) -> Option<i64> {
+    if number.fract() != 0.0 || number.abs() >= i64::MAX as f64 {
+        return None;
+    }
+
+    return Some(number as i64);
 }
 
 impl From<&LiteralExpr> for RuntimeValue {
@@ -61,11 +193,9 @@ pub enum RuntimeError {
         details: Option<String>,
     },
 
-    #[error("undefined variable: {name:#?}. Details = {details:?}")]
-    UndefinedVariable {
-        name: Token,
-        details: Option<String>,
-    },
+    // Reported at the reference, however deep in calls it is
+    #[error("[line {}] Undefined variable '{}'", .name.line, .name.lexeme)]
+    UndefinedVariable { name: Token },
 
     #[error("undefined property: {name:#?}. Details = {details:?}")]
     UndefinedProperty {
@@ -79,13 +209,274 @@ pub enum RuntimeError {
         details: Option<String>,
     },
 
-    #[error("function expected {expected} args, but call found {found}. Details = {details:?}")]
+    #[error("[line {line}] {callee} expected {expected} but got {found}")]
     WrongNumberOfArgs {
-        expected: usize,
+        callee: LoxStr,
+        expected: LoxStr,
+        found: usize,
+        line: usize,
+    },
+
+    // Like `WrongNumberOfArgs`, for a call made by `apply`
+    #[error("{callee} expected {expected} but the list has {found}")]
+    WrongNumberOfListArgs {
+        callee: LoxStr,
+        expected: LoxStr,
         found: usize,
-        details: Option<String>,
     },
 
-    #[error("non-error return short-circuit")]
-    NonErrorReturnShortCircuit { value: Option<RuntimeValue> },
+    #[error("{callee}() expected {expected} but got {found}")]
+    InvalidArgument {
+        callee: LoxStr,
+        expected: LoxStr,
+        found: LoxStr,
+    },
+
+    #[error("{callee}() expected its lower bound to be no greater than its upper bound")]
+    ReversedBounds { callee: LoxStr },
+
+    #[error("[line {line}] Exceeded the limit of {max} loop iterations")]
+    IterationLimitExceeded { max: usize, line: usize },
+
+    #[error("[line {line}] Exceeded the limit of {max} nested calls")]
+    CallDepthExceeded { max: usize, line: usize },
+
+    #[error("Exceeded the limit of {max} for the length of a {type_name}")]
+    LengthLimitExceeded { type_name: LoxStr, max: usize },
+
+    #[error("[line {line}] Can't apply '{operator}' to {left} and {right}")]
+    InvalidOperands {
+        operator: LoxStr,
+        left: LoxStr,
+        right: LoxStr,
+        line: usize,
+    },
+
+    #[error("[line {line}] Condition must be a boolean but got {found}")]
+    NonBooleanCondition { found: LoxStr, line: usize },
+
+    #[error("[line {line}] {what} must be a non-negative whole number but got {found}")]
+    InvalidIndex {
+        what: LoxStr,
+        found: LoxStr,
+        line: usize,
+    },
+
+    #[error("Expected a value of type {expected} but got {found}")]
+    TypeAssertion { expected: LoxStr, found: LoxStr },
+
+    #[error("[line {line}] Could not import \"{path}\": {details}")]
+    ImportFailed {
+        path: LoxStr,
+        details: String,
+        line: usize,
+    },
+
+    #[error("Could not {action} \"{path}\": {details}")]
+    FileAccess {
+        action: LoxStr,
+        path: LoxStr,
+        details: String,
+    },
+
+    #[error("[line {line}] Circular import of \"{path}\"")]
+    CircularImport { path: LoxStr, line: usize },
+
+    // Carries the value a script threw, for `catch` to bind
+    #[error("[line {line}] Uncaught error: {message}")]
+    Thrown {
+        value: RuntimeValue,
+        message: LoxStr,
+        line: usize,
+    },
+
+    #[error("[line {line}] Can only iterate over lists and maps but got {found}")]
+    NotIterable { found: LoxStr, line: usize },
+
+    #[error("[line {line}] Can't set '{name}' on a frozen {class} instance")]
+    FrozenInstance {
+        name: LoxStr,
+        class: LoxStr,
+        line: usize,
+    },
+
+    #[error("Can't delete '{name}' from a frozen {class} instance")]
+    FrozenInstanceDelete { name: LoxStr, class: LoxStr },
+
+    #[error("[line {line}] Superclass '{name}' must be a class")]
+    InvalidSuperclass { name: LoxStr, line: usize },
+
+    #[error("{callee}() expected a whole number but got {found}")]
+    NotWholeNumber { callee: LoxStr, found: LoxStr },
+
+    #[error("[line {line}] Can only apply '{operator}' to a number but got {found}")]
+    InvalidUpdateExpr {
+        operator: LoxStr,
+        found: LoxStr,
+        line: usize,
+    },
+
+    #[error("[line {line}] Class '{class}' has no static method '{name}'")]
+    UndefinedStaticMethod {
+        class: LoxStr,
+        name: LoxStr,
+        line: usize,
+    },
+
+    #[error("{callee}() expected a function but got {found}")]
+    InvalidCallback { callee: LoxStr, found: LoxStr },
+
+    #[error("{callee}() expected a number but got {found}")]
+    NotANumber { callee: LoxStr, found: LoxStr },
+
+    #[error("{callee}() calls its callback with 1 argument but {callback} expects {expected}")]
+    CallbackArity {
+        callee: LoxStr,
+        callback: LoxStr,
+        expected: LoxStr,
+    },
+
+    #[error("{callee}() can't divide by zero")]
+    DivisionByZero { callee: LoxStr },
+
+    #[error("{callee}() can't convert {found}")]
+    InvalidConversion { callee: LoxStr, found: LoxStr },
+
+    #[error("Invalid range: {details}")]
+    InvalidRange { details: LoxStr },
+
+    #[error("[line {line}] Variable '{name}' is read before it's assigned")]
+    UninitializedVariable { name: LoxStr, line: usize },
+
+    #[error("[line {line}] Global '{name}' is already defined")]
+    AlreadyDefined { name: LoxStr, line: usize },
+
+    #[error("Undefined global '{name}'")]
+    UndefinedGlobal { name: LoxStr },
+
+    #[error("Global '{name}' isn't a function or class")]
+    NotCallableGlobal { name: LoxStr },
+
+    #[error("[line {line}] A {type_name} has no method '{name}'")]
+    UndefinedPrimitiveMethod {
+        type_name: LoxStr,
+        name: LoxStr,
+        line: usize,
+    },
+
+    #[error("Can't use {found} as a map key")]
+    InvalidMapKey { found: LoxStr },
+
+    #[error("Unknown type name '{name}'")]
+    UnknownTypeName { name: LoxStr },
+
+    // Only reachable with the resolver turned off, which otherwise rejects these up front
+    #[error("{details}")]
+    StrayControlFlow { details: &'static str },
+
+    // An error that doesn't know its own line, with that of the statement it stopped
+    #[error("[line {line}] {error}")]
+    AtLine {
+        line: usize,
+        error: Box<RuntimeError>,
+    },
 }
+
+impl RuntimeError {
+    // The line the message starts with, eg. "[line 3] Undefined variable 'a'". Every variant
+    // is listed, so a new one has to decide whether it has a line
+    pub fn line(&self) -> Option<usize> {
+        return match self {
+            Self::UndefinedVariable { name } => Some(name.line),
+
+            Self::WrongNumberOfArgs { line, .. }
+            | Self::IterationLimitExceeded { line, .. }
+            | Self::CallDepthExceeded { line, .. }
+            | Self::InvalidOperands { line, .. }
+            | Self::NonBooleanCondition { line, .. }
+            | Self::InvalidIndex { line, .. }
+            | Self::ImportFailed { line, .. }
+            | Self::CircularImport { line, .. }
+            | Self::Thrown { line, .. }
+            | Self::NotIterable { line, .. }
+            | Self::FrozenInstance { line, .. }
+            | Self::InvalidSuperclass { line, .. }
+            | Self::InvalidUpdateExpr { line, .. }
+            | Self::UndefinedStaticMethod { line, .. }
+            | Self::UninitializedVariable { line, .. }
+            | Self::AlreadyDefined { line, .. }
+            | Self::UndefinedPrimitiveMethod { line, .. }
+            | Self::AtLine { line, .. } => Some(*line),
+
+            Self::InvalidUnaryExpr { .. }
+            | Self::InvalidBinaryExpr { .. }
+            | Self::InvalidGetExpr { .. }
+            | Self::InvalidSetExpr { .. }
+            | Self::UndefinedProperty { .. }
+            | Self::InvalidCallable { .. }
+            | Self::WrongNumberOfListArgs { .. }
+            | Self::InvalidArgument { .. }
+            | Self::ReversedBounds { .. }
+            | Self::FrozenInstanceDelete { .. }
+            | Self::LengthLimitExceeded { .. }
+            | Self::TypeAssertion { .. }
+            | Self::FileAccess { .. }
+            | Self::NotWholeNumber { .. }
+            | Self::InvalidCallback { .. }
+            | Self::NotANumber { .. }
+            | Self::CallbackArity { .. }
+            | Self::DivisionByZero { .. }
+            | Self::InvalidConversion { .. }
+            | Self::InvalidRange { .. }
+            | Self::UndefinedGlobal { .. }
+            | Self::NotCallableGlobal { .. }
+            | Self::InvalidMapKey { .. }
+            | Self::UnknownTypeName { .. }
+            | Self::StrayControlFlow { .. } => None,
+        };
+    }
+
+    // Prefixes the message with `line` when it doesn't have one, eg. for an error in a native
+    pub fn at_line(self, line: Option<usize>) -> Self {
+        return match line {
+            Some(line) if self.line().is_none() => Self::AtLine {
+                line,
+                error: Box::new(self),
+            },
+            _ => self,
+        };
+    }
+}
+
+// Unwinds execution, either for a genuine error or to carry out a `return` / `break` /
+// `continue`, which stop at their enclosing function / loop and so never reach the top level
+// unless the resolver is turned off
+#[derive(Debug)]
+pub enum ControlFlow {
+    Error(RuntimeError),
+    Return(Option<RuntimeValue>),
+    Break(Option<RuntimeValue>),
+    Continue,
+}
+
+impl ControlFlow {
+    // For a `return` / `break` / `continue` with nothing left to stop it
+    pub fn into_error(self) -> RuntimeError {
+        let details = match self {
+            Self::Error(e) => return e,
+            Self::Return(_) => "Can't return from top-level code",
+            Self::Break(_) => "Can't break outside of a loop",
+            Self::Continue => "Can't continue outside of a loop",
+        };
+
+        return RuntimeError::StrayControlFlow { details };
+    }
+}
+
+impl From<RuntimeError> for ControlFlow {
+    fn from(error: RuntimeError) -> Self {
+        return Self::Error(error);
+    }
+}
+
+pub type ControlFlowResult<T = ()> = Result<T, ControlFlow>;
@@ -2,57 +2,136 @@ use crate::{lox, string::LoxStr, token::Token, token_type::TokenType};
 
 use std::{collections::HashMap, iter::Iterator};
 
-use lazy_static::lazy_static;
-
-lazy_static! {
-    static ref KEYWORDS: HashMap<String, TokenType> = {
+// Thread-local rather than a static since `TokenType` holds `Rc`s
+thread_local! {
+    static KEYWORDS: HashMap<String, TokenType> = {
         let mut keywords = HashMap::new();
         keywords.insert(String::from("and"), TokenType::And);
+        keywords.insert(String::from("as"), TokenType::As);
+        keywords.insert(String::from("break"), TokenType::Break);
+        keywords.insert(String::from("continue"), TokenType::Continue);
+        keywords.insert(String::from("catch"), TokenType::Catch);
         keywords.insert(String::from("class"), TokenType::Class);
+        keywords.insert(String::from("debugger"), TokenType::Debugger);
         keywords.insert(String::from("else"), TokenType::Else);
         keywords.insert(String::from("false"), TokenType::False);
         keywords.insert(String::from("for"), TokenType::For);
         keywords.insert(String::from("fun"), TokenType::Fun);
         keywords.insert(String::from("if"), TokenType::If);
+        keywords.insert(String::from("import"), TokenType::Import);
+        keywords.insert(String::from("in"), TokenType::In);
+        keywords.insert(String::from("loop"), TokenType::Loop);
         keywords.insert(String::from("nil"), TokenType::Nil);
         keywords.insert(String::from("or"), TokenType::Or);
         keywords.insert(String::from("print"), TokenType::Print);
+        keywords.insert(String::from("repeat"), TokenType::Repeat);
         keywords.insert(String::from("return"), TokenType::Return);
         keywords.insert(String::from("super"), TokenType::Super);
         keywords.insert(String::from("this"), TokenType::This);
+        keywords.insert(String::from("throw"), TokenType::Throw);
         keywords.insert(String::from("true"), TokenType::True);
+        keywords.insert(String::from("try"), TokenType::Try);
         keywords.insert(String::from("var"), TokenType::Var);
         keywords.insert(String::from("while"), TokenType::While);
+        keywords.insert(String::from("with"), TokenType::With);
+        keywords.insert(String::from("xor"), TokenType::Xor);
         keywords
     };
 }
 
+// The scanner indexes by char, so multi-byte characters are kept whole and columns count
+// characters. Most scripts are pure ASCII though, where each byte is a char, so those
+// are indexed directly rather than copied into a `Vec<char>` at 4 bytes a char.
+enum Source {
+    Ascii(LoxStr),
+    Chars(Vec<char>),
+}
+
+impl Source {
+    fn new(source: LoxStr) -> Self {
+        if source.is_ascii() {
+            return Self::Ascii(source);
+        }
+
+        return Self::Chars(source.chars().collect());
+    }
+
+    fn len(&self) -> usize {
+        return match self {
+            Self::Ascii(source) => source.len(),
+            Self::Chars(chars) => chars.len(),
+        };
+    }
+
+    fn char_at(&self, index: usize) -> char {
+        return match self {
+            Self::Ascii(source) => source.as_bytes()[index] as char,
+            Self::Chars(chars) => chars[index],
+        };
+    }
+
+    fn text(&self, start: usize, end: usize) -> String {
+        return match self {
+            Self::Ascii(source) => source[start..end].to_string(),
+            Self::Chars(chars) => chars[start..end].iter().collect(),
+        };
+    }
+}
+
 pub struct Scanner {
-    source: LoxStr,
-    source_chars: Vec<char>,
+    source: Source,
     tokens: Vec<Token>,
 
     start: usize,
     current: usize,
     line: usize,
+    // Where the current line, and the token being scanned, begin
+    line_start: usize,
+    start_column: usize,
+
+    keep_comments: bool,
+    insert_semicolons: bool,
 }
 
 impl Scanner {
     pub fn new(source: LoxStr) -> Self {
         return Self {
-            source_chars: source.chars().collect(),
-            source,
+            source: Source::new(source),
             tokens: Vec::new(),
 
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            start_column: 1,
+
+            keep_comments: false,
+            insert_semicolons: false,
         };
     }
 
+    // Comments then become tokens rather than being skipped, eg. for a formatter.
+    // The parser drops them again, so the tokens can still be parsed
+    pub fn keeping_comments(mut self) -> Self {
+        self.keep_comments = true;
+        return self;
+    }
+
+    // A line break then ends a statement, as if there were a `;`, when the line's last token
+    // can end one and it isn't inside parentheses or brackets. So a multi-line argument list
+    // still works, while a function passed in one still needs no semicolons in its body.
+    // A line starting with `.` or `?.` carries on the one before, for chained calls, and
+    // nothing is inserted before a `{` or after the condition of an `if`, loop and so on.
+    // `var f = fun () { ... }` still needs its `;`, since a `}` usually ends a block
+    pub fn inserting_semicolons(mut self) -> Self {
+        self.insert_semicolons = true;
+        return self;
+    }
+
     pub fn scan_tokens(mut self) -> Vec<Token> {
         while !self.is_at_end() {
             self.start = self.current;
+            self.start_column = self.start - self.line_start + 1;
             self.scan_token();
         }
 
@@ -60,8 +139,13 @@ impl Scanner {
             token_type: TokenType::EOF,
             lexeme: "".into(),
             line: self.line,
+            column: self.current - self.line_start + 1,
         });
 
+        if self.insert_semicolons {
+            return insert_semicolons(self.tokens);
+        }
+
         return self.tokens;
     }
 
@@ -73,10 +157,22 @@ impl Scanner {
             ')' => Some(TokenType::RightParen),
             '{' => Some(TokenType::LeftBrace),
             '}' => Some(TokenType::RightBrace),
+            '[' => Some(TokenType::LeftBracket),
+            ']' => Some(TokenType::RightBracket),
             ',' => Some(TokenType::Comma),
             '.' => Some(TokenType::Dot),
-            '-' => Some(TokenType::Minus),
-            '+' => Some(TokenType::Plus),
+            '-' => Some(if self.match_next('-') {
+                TokenType::MinusMinus
+            } else {
+                TokenType::Minus
+            }),
+
+            '+' => Some(if self.match_next('+') {
+                TokenType::PlusPlus
+            } else {
+                TokenType::Plus
+            }),
+
             ';' => Some(TokenType::Semicolon),
             '*' => Some(TokenType::Star),
 
@@ -104,12 +200,20 @@ impl Scanner {
                 TokenType::Greater
             }),
 
+            '?' if self.match_next('.') => Some(TokenType::QuestionDot),
+            '?' if self.match_next('?') => Some(TokenType::QuestionQuestion),
+
             '/' => {
                 if self.match_next('/') {
                     while self.peek() != Some('\n') && !self.is_at_end() {
                         self.advance();
                     }
 
+                    self.keep_comments.then(|| {
+                        TokenType::LineComment(self.text(self.start + 2, self.current).into())
+                    })
+                } else if self.match_next('*') {
+                    self.block_comment();
                     None
                 } else {
                     Some(TokenType::Slash)
@@ -120,7 +224,7 @@ impl Scanner {
             ' ' | '\r' | '\t' => None,
 
             '\n' => {
-                self.line += 1;
+                self.new_line();
                 None
             }
 
@@ -131,7 +235,7 @@ impl Scanner {
             c if self.is_alpha(c) => Some(self.identifier()),
 
             _ => {
-                lox::error(self.line, "Unexpected character.");
+                lox::error(self.line, Some(self.start_column), "Unexpected character.");
                 None
             }
         };
@@ -141,28 +245,81 @@ impl Scanner {
         }
     }
 
+    // Block comments don't nest, so the first `*/` ends one
+    fn block_comment(&mut self) {
+        // A comment spanning lines belongs to the line it starts on
+        let line = self.line;
+
+        loop {
+            if self.is_at_end() {
+                lox::error(line, Some(self.start_column), "Unterminated block comment.");
+                return;
+            }
+
+            let c = self.advance();
+
+            if c == '\n' {
+                self.new_line();
+            } else if c == '*' && self.match_next('/') {
+                break;
+            }
+        }
+
+        if self.keep_comments {
+            let text = self.text(self.start + 2, self.current - 2);
+
+            self.tokens.push(Token {
+                token_type: TokenType::BlockComment(text.into()),
+                lexeme: self.text(self.start, self.current).into(),
+                line,
+                column: self.start_column,
+            });
+        }
+    }
+
     fn string(&mut self) -> TokenType {
         while self.peek() != Some('"') && !self.is_at_end() {
-            if self.peek() == Some('\n') {
-                self.line += 1;
-            }
+            let c = self.advance();
 
-            self.advance();
+            if c == '\n' {
+                self.new_line();
+            }
         }
 
         if self.is_at_end() {
-            lox::error(self.line, "Unterminated string.");
+            lox::error(self.line, None, "Unterminated string.");
         }
 
         // The closing ".
         self.advance();
 
         // Trim the surrounding quotes
-        let value = self.source[self.start + 1..self.current - 1].to_string();
+        let value = self.text(self.start + 1, self.current - 1);
+
+        // A string spanning lines holds the same text whether the file uses LF or CRLF
+        if value.contains('\r') {
+            return TokenType::String(value.replace("\r\n", "\n").into());
+        }
+
         return TokenType::String(value.into());
     }
 
     fn number(&mut self) -> TokenType {
+        // `0x` / `0b` literals, eg. `0xFF` and `0b1010`
+        if self.source.char_at(self.start) == '0' {
+            let radix = match self.peek() {
+                Some('x' | 'X') => Some(16),
+                Some('b' | 'B') => Some(2),
+                _ => None,
+            };
+
+            if let Some(radix) = radix {
+                if self.peek_next().is_some_and(|next| next.is_digit(radix)) {
+                    return self.radix_number(radix);
+                }
+            }
+        }
+
         while let Some(peek) = self.peek() {
             if !self.is_digit(peek) {
                 break;
@@ -189,11 +346,33 @@ impl Scanner {
             }
         }
 
-        return TokenType::Number(
-            self.source[self.start..self.current]
-                .parse::<f64>()
-                .unwrap(),
-        );
+        return TokenType::Number(self.text(self.start, self.current).parse::<f64>().unwrap());
+    }
+
+    fn radix_number(&mut self, radix: u32) -> TokenType {
+        // Consume the "0x" / "0b"
+        self.advance();
+
+        while let Some(peek) = self.peek() {
+            if !peek.is_digit(radix) {
+                break;
+            }
+
+            self.advance();
+        }
+
+        let digits = self.text(self.start + 2, self.current);
+
+        let Ok(value) = u64::from_str_radix(&digits, radix) else {
+            lox::error(
+                self.line,
+                Some(self.start_column),
+                "Number literal is too large.",
+            );
+            return TokenType::Number(0.0);
+        };
+
+        return TokenType::Number(value as f64);
     }
 
     fn identifier(&mut self) -> TokenType {
@@ -205,8 +384,13 @@ impl Scanner {
             self.advance();
         }
 
-        let text = &self.source[self.start..self.current];
-        return KEYWORDS.get(text).cloned().unwrap_or(TokenType::Identifier);
+        let text = self.text(self.start, self.current);
+        return KEYWORDS.with(|keywords| {
+            keywords
+                .get(&text)
+                .cloned()
+                .unwrap_or(TokenType::Identifier)
+        });
     }
 
     fn match_next(&mut self, expected: char) -> bool {
@@ -214,7 +398,7 @@ impl Scanner {
             return false;
         }
 
-        if self.source_chars[self.current] != expected {
+        if self.source.char_at(self.current) != expected {
             return false;
         }
 
@@ -228,7 +412,7 @@ impl Scanner {
             return None;
         }
 
-        return Some(self.source_chars[self.current]);
+        return Some(self.source.char_at(self.current));
     }
 
     fn peek_next(&self) -> Option<char> {
@@ -236,19 +420,20 @@ impl Scanner {
             return None;
         }
 
-        return Some(self.source_chars[self.current + 1]);
+        return Some(self.source.char_at(self.current + 1));
     }
 
+    // Identifiers may use any Unicode letter, eg. `café`
     fn is_alpha(&self, c: char) -> bool {
-        return (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || c == '_';
+        return c.is_alphabetic() || c == '_';
     }
 
     fn is_digit(&self, c: char) -> bool {
-        return c >= '0' && c <= '9';
+        return c.is_ascii_digit();
     }
 
     fn is_alpha_numeric(&self, c: char) -> bool {
-        return self.is_alpha(c) || self.is_digit(c);
+        return c.is_alphanumeric() || c == '_';
     }
 
     fn is_at_end(&self) -> bool {
@@ -256,20 +441,148 @@ impl Scanner {
     }
 
     fn advance(&mut self) -> char {
-        let c = self.source_chars[self.current];
+        let c = self.source.char_at(self.current);
 
         self.current += 1;
 
         return c;
     }
 
+    fn text(&self, start: usize, end: usize) -> String {
+        return self.source.text(start, end);
+    }
+
     fn add_token(&mut self, token_type: TokenType) {
-        let text = &self.source[self.start..self.current];
+        let text = self.text(self.start, self.current);
 
         self.tokens.push(Token {
             token_type,
-            lexeme: text.to_string().into(),
+            lexeme: text.into(),
             line: self.line,
+            column: self.start_column,
         });
     }
+
+    // Call once the '\n' has been consumed
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
+    }
+}
+
+// What a bracket is open for, as far as ending statements goes
+#[derive(Clone, Copy, PartialEq)]
+enum Bracket {
+    // A statement's parenthesized condition or clauses, eg. `if (...)`
+    Condition,
+    // An anonymous function's parameters, eg. `fun (...)`
+    FunctionParams,
+    // An anonymous function's body, which ends the expression it's in like a `)` would
+    FunctionBody,
+    Other,
+}
+
+fn insert_semicolons(tokens: Vec<Token>) -> Vec<Token> {
+    let is_code = |token: &Token| {
+        return !matches!(
+            token.token_type,
+            TokenType::LineComment(_) | TokenType::BlockComment(_)
+        );
+    };
+
+    let mut result = Vec::with_capacity(tokens.len());
+    // The brackets open at each token, innermost last, with what they're open for
+    let mut open: Vec<(TokenType, Bracket)> = vec![];
+    let mut previous: Option<&TokenType> = None;
+    let mut after_function_params = false;
+
+    for (i, token) in tokens.iter().enumerate() {
+        result.push(token.clone());
+
+        if !is_code(token) {
+            continue;
+        }
+
+        let mut closed = None;
+        match token.token_type {
+            TokenType::LeftBrace if after_function_params => {
+                open.push((token.token_type.clone(), Bracket::FunctionBody));
+            }
+            TokenType::LeftParen if previous == Some(&TokenType::Fun) => {
+                open.push((token.token_type.clone(), Bracket::FunctionParams));
+            }
+            TokenType::LeftParen | TokenType::LeftBracket | TokenType::LeftBrace => {
+                let condition = matches!(
+                    previous,
+                    Some(
+                        TokenType::If
+                            | TokenType::While
+                            | TokenType::For
+                            | TokenType::Repeat
+                            | TokenType::With
+                            | TokenType::Catch
+                    )
+                );
+                let bracket = if condition {
+                    Bracket::Condition
+                } else {
+                    Bracket::Other
+                };
+                open.push((token.token_type.clone(), bracket));
+            }
+            TokenType::RightParen | TokenType::RightBracket | TokenType::RightBrace => {
+                closed = open.pop().map(|(_, bracket)| bracket);
+            }
+            _ => {}
+        }
+        previous = Some(&token.token_type);
+        after_function_params = closed == Some(Bracket::FunctionParams);
+
+        let Some(next) = tokens[i + 1..].iter().find(|token| is_code(token)) else {
+            continue;
+        };
+
+        let ends_line = match next.token_type {
+            // A block's last statement can end at its closing brace, eg. `{ return x }`
+            TokenType::EOF | TokenType::RightBrace => true,
+            TokenType::Dot | TokenType::QuestionDot | TokenType::LeftBrace => false,
+            _ => next.line > token.line,
+        };
+
+        if ends_line
+            && closed != Some(Bracket::Condition)
+            && matches!(open.last(), None | Some((TokenType::LeftBrace, _)))
+            && (can_end_statement(&token.token_type) || closed == Some(Bracket::FunctionBody))
+        {
+            result.push(Token {
+                token_type: TokenType::Semicolon,
+                lexeme: ";".into(),
+                line: token.line,
+                column: token.column + token.lexeme.chars().count(),
+            });
+        }
+    }
+
+    return result;
+}
+
+fn can_end_statement(token_type: &TokenType) -> bool {
+    return matches!(
+        token_type,
+        TokenType::Identifier
+            | TokenType::String(_)
+            | TokenType::Number(_)
+            | TokenType::True
+            | TokenType::False
+            | TokenType::Nil
+            | TokenType::This
+            | TokenType::RightParen
+            | TokenType::RightBracket
+            | TokenType::PlusPlus
+            | TokenType::MinusMinus
+            | TokenType::Return
+            | TokenType::Break
+            | TokenType::Continue
+            | TokenType::Debugger
+    );
 }
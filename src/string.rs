@@ -1,3 +1,5 @@
-use std::borrow::Cow;
+use std::rc::Rc;
 
-pub type LoxStr = Cow<'static, str>;
+// Reference-counted so cloning a string value (eg. reading it out
+// of an environment) shares the buffer instead of copying it
+pub type LoxStr = Rc<str>;
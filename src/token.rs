@@ -5,4 +5,15 @@ pub struct Token {
     pub token_type: TokenType,
     pub lexeme: LoxStr,
     pub line: usize,
+    // Counted in chars from 1. Tokens the parser makes up have no position, so use 0
+    pub column: usize,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self.token_type {
+            TokenType::Identifier => f.write_str(&self.lexeme),
+            _ => write!(f, "{}", self.token_type),
+        };
+    }
 }
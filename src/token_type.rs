@@ -7,6 +7,8 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
@@ -24,29 +26,128 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    MinusMinus,
+    PlusPlus,
+    QuestionDot,
+    QuestionQuestion,
 
     // Literals
     Identifier,
     String(LoxStr),
     Number(f64),
 
+    // Only produced when the scanner is keeping comments, holding the text between the delimiters
+    LineComment(LoxStr),
+    BlockComment(LoxStr),
+
     // Keywords
     And,
+    As,
+    Break,
+    Catch,
     Class,
+    Continue,
+    Debugger,
     Else,
     False,
     Fun,
     For,
     If,
+    Import,
+    In,
+    Loop,
     Nil,
     Or,
     Print,
+    Repeat,
     Return,
     Super,
     This,
+    Throw,
     True,
+    Try,
     Var,
     While,
+    With,
+    Xor,
 
     EOF,
 }
+
+// The source text of the token, so tokens can be printed back out as a program. A number
+// shows the shortest literal that scans back to the same value, and a string its text as
+// written, since Lox strings have no escapes. Identifiers have no fixed text, so `Token`
+// shows their lexeme instead
+impl std::fmt::Display for TokenType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            Self::LeftParen => "(",
+            Self::RightParen => ")",
+            Self::LeftBrace => "{",
+            Self::RightBrace => "}",
+            Self::LeftBracket => "[",
+            Self::RightBracket => "]",
+            Self::Comma => ",",
+            Self::Dot => ".",
+            Self::Minus => "-",
+            Self::Plus => "+",
+            Self::Semicolon => ";",
+            Self::Slash => "/",
+            Self::Star => "*",
+
+            Self::Bang => "!",
+            Self::BangEqual => "!=",
+            Self::Equal => "=",
+            Self::EqualEqual => "==",
+            Self::Greater => ">",
+            Self::GreaterEqual => ">=",
+            Self::Less => "<",
+            Self::LessEqual => "<=",
+            Self::MinusMinus => "--",
+            Self::PlusPlus => "++",
+            Self::QuestionDot => "?.",
+            Self::QuestionQuestion => "??",
+
+            Self::Identifier => "identifier",
+            Self::String(string) => return write!(f, "\"{string}\""),
+            Self::Number(number) => return write!(f, "{number}"),
+
+            Self::LineComment(text) => return write!(f, "//{text}"),
+            Self::BlockComment(text) => return write!(f, "/*{text}*/"),
+
+            Self::And => "and",
+            Self::As => "as",
+            Self::Break => "break",
+            Self::Catch => "catch",
+            Self::Class => "class",
+            Self::Continue => "continue",
+            Self::Debugger => "debugger",
+            Self::Else => "else",
+            Self::False => "false",
+            Self::Fun => "fun",
+            Self::For => "for",
+            Self::If => "if",
+            Self::Import => "import",
+            Self::In => "in",
+            Self::Loop => "loop",
+            Self::Nil => "nil",
+            Self::Or => "or",
+            Self::Print => "print",
+            Self::Repeat => "repeat",
+            Self::Return => "return",
+            Self::Super => "super",
+            Self::This => "this",
+            Self::Throw => "throw",
+            Self::True => "true",
+            Self::Try => "try",
+            Self::Var => "var",
+            Self::While => "while",
+            Self::With => "with",
+            Self::Xor => "xor",
+
+            Self::EOF => "end of file",
+        };
+
+        return f.write_str(text);
+    }
+}